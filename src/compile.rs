@@ -0,0 +1,44 @@
+//! Ahead-of-time compilation of scripts to other languages and formats.
+use crate::ir::{Op, Program};
+use crate::{CellKind, EofBehaviour};
+
+pub mod c;
+pub mod elf;
+pub mod rust;
+pub mod wasm;
+
+pub struct CompileOptions {
+    pub cell: CellKind,
+    /// Number of cells on the tape. Moving outside of it stops the program with an error.
+    pub tape_size: usize,
+    pub eof: EofBehaviour,
+}
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            cell: CellKind::default(),
+            tape_size: 30000,
+            eof: EofBehaviour::default(),
+        }
+    }
+}
+
+/// Iterates over the operations of a program along with how deeply nested in loops they are.
+/// Both brackets of a loop have the depth of the code around the loop.
+fn nested_ops(program: &Program) -> impl Iterator<Item = (usize, Op)> + '_ {
+    let mut depth = 0usize;
+    program.instructions.iter().map(move |instruction| {
+        let op = instruction.op;
+        match op {
+            Op::JumpIfZero(_) => {
+                depth += 1;
+                (depth - 1, op)
+            }
+            Op::JumpIfNonzero(_) => {
+                depth -= 1;
+                (depth, op)
+            }
+            _ => (depth, op),
+        }
+    })
+}
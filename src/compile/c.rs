@@ -0,0 +1,96 @@
+//! Generates a self-contained C program from a script.
+use crate::compile::{nested_ops, CompileOptions};
+use crate::ir::{Op, Program};
+use crate::{CellKind, EofBehaviour};
+use std::fmt::Write;
+
+pub fn generate(program: &Program, options: &CompileOptions) -> String {
+    let cell_type = match options.cell {
+        CellKind::U8 => "uint8_t",
+        CellKind::U16 => "uint16_t",
+        CellKind::U32 => "uint32_t",
+        CellKind::U64 => "uint64_t",
+    };
+    let on_eof = match options.eof {
+        EofBehaviour::Zero => "*cell = 0;",
+        EofBehaviour::MinusOne => "*cell = (cell_t)-1;",
+        EofBehaviour::Unchanged => "",
+        EofBehaviour::Error => "fail(\"read past the end of input\");",
+    };
+
+    let mut out = String::new();
+    // Writing to a String cannot fail, so the results are ignored throughout.
+    let _ = write!(
+        out,
+        r#"/* Generated by brainfuck. */
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+typedef {cell_type} cell_t;
+
+#define TAPE_SIZE ((size_t){tape_size})
+
+static cell_t tape[TAPE_SIZE];
+
+static inline void fail(const char *message) {{
+    fflush(stdout);
+    fprintf(stderr, "error: %s\n", message);
+    exit(1);
+}}
+
+static inline size_t offset(size_t pointer, long long amount) {{
+    size_t result = pointer + (size_t)amount;
+    if (result >= TAPE_SIZE) {{
+        fail("data pointer moved outside of the tape");
+    }}
+    return result;
+}}
+
+static inline void output(cell_t value) {{
+    for (int shift = (int)sizeof(cell_t) * 8 - 8; shift >= 0; shift -= 8) {{
+        putchar((int)((value >> shift) & 0xff));
+    }}
+}}
+
+static inline void input(cell_t *cell) {{
+    int ch = getchar();
+    if (ch == EOF) {{
+        {on_eof}
+    }} else {{
+        *cell = (cell_t)ch;
+    }}
+}}
+
+int main(void) {{
+    size_t p = 0;
+"#,
+        tape_size = options.tape_size,
+    );
+
+    for (depth, op) in nested_ops(program) {
+        let indent = "    ".repeat(depth + 1);
+        let _ = match op {
+            Op::Add(amount) => writeln!(out, "{indent}tape[p] += (cell_t){amount}LL;"),
+            Op::Move(amount) => writeln!(out, "{indent}p = offset(p, {amount}LL);"),
+            Op::Output => writeln!(out, "{indent}output(tape[p]);"),
+            Op::Input => writeln!(out, "{indent}input(&tape[p]);"),
+            Op::JumpIfZero(_) => writeln!(out, "{indent}while (tape[p]) {{"),
+            Op::JumpIfNonzero(_) => writeln!(out, "{indent}}}"),
+            Op::Clear => writeln!(out, "{indent}tape[p] = 0;"),
+            Op::MulAdd { offset, factor } => writeln!(
+                out,
+                "{indent}if (tape[p]) tape[offset(p, {offset}LL)] += (cell_t)((uint64_t)tape[p] * (uint64_t){factor}LL);"
+            ),
+            Op::Scan(step) => writeln!(out, "{indent}while (tape[p]) p = offset(p, {step}LL);"),
+        };
+    }
+
+    out.push_str(
+        r#"    fflush(stdout);
+    return 0;
+}
+"#,
+    );
+    out
+}
@@ -0,0 +1,461 @@
+//! Generates a static x86-64 Linux ELF executable from a script, without an assembler or linker.
+//!
+//! Register usage:
+//! - `rbx`: address of the current cell,
+//! - `r12`/`r13`: start and end of the tape,
+//! - `r14`: scratch buffer used for input and multi-byte output.
+use crate::compile::CompileOptions;
+use crate::ir::{Op, Program};
+use crate::{CellKind, EofBehaviour};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+const BASE_ADDRESS: u64 = 0x400000;
+const PAGE_SIZE: u64 = 0x1000;
+const ELF_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+const HEADERS_SIZE: usize = ELF_HEADER_SIZE + 2 * PROGRAM_HEADER_SIZE;
+/// Size of the scratch buffer placed before the tape.
+const SCRATCH_SIZE: u64 = 16;
+
+const TAPE_ERROR: &[u8] = b"error: data pointer moved outside of the tape\n";
+const EOF_ERROR: &[u8] = b"error: read past the end of input\n";
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+enum Label {
+    TapeError,
+    EofError,
+    TapeErrorMessage,
+    EofErrorMessage,
+    /// Start of the body of the loop opened at the IR index.
+    LoopBody(usize),
+    /// Just after the end of the loop opened at the IR index.
+    LoopEnd(usize),
+}
+
+struct Assembler {
+    code: Vec<u8>,
+    cell_size: u8,
+    /// Known label addresses, as offsets into `code`.
+    labels: HashMap<Label, usize>,
+    /// Places where a 32-bit offset relative to the end of it should point at a label.
+    fixups: Vec<(usize, Label)>,
+}
+
+impl Assembler {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+    fn imm32(&mut self, value: i32) {
+        self.bytes(&value.to_le_bytes());
+    }
+    fn imm64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+    fn label(&mut self, label: Label) {
+        self.labels.insert(label, self.code.len());
+    }
+    fn rel32(&mut self, label: Label) {
+        self.fixups.push((self.code.len(), label));
+        self.imm32(0);
+    }
+    /// Emits an operand-size prefix (and REX.W) for operations on a cell.
+    fn cell_prefix(&mut self) {
+        match self.cell_size {
+            2 => self.bytes(&[0x66]),
+            8 => self.bytes(&[0x48]),
+            _ => {}
+        }
+    }
+    /// The byte-sized variant of an opcode is one less than the wider variants.
+    fn cell_opcode(&self, wide: u8) -> u8 {
+        if self.cell_size == 1 {
+            wide - 1
+        } else {
+            wide
+        }
+    }
+
+    /// `cmp <cell> [rbx], 0`
+    fn compare_cell_to_zero(&mut self) {
+        if self.cell_size == 1 {
+            self.bytes(&[0x80, 0x3B, 0x00]);
+        } else {
+            self.cell_prefix();
+            self.bytes(&[0x83, 0x3B, 0x00]);
+        }
+    }
+    /// `add <cell> [reg], rax` where `modrm` selects the register holding the address.
+    fn add_rax_to_cell(&mut self, modrm: u8) {
+        self.cell_prefix();
+        let opcode = self.cell_opcode(0x01);
+        self.bytes(&[opcode, modrm]);
+    }
+    /// `mov <cell> [rbx], rax`
+    fn store_rax_to_cell(&mut self) {
+        self.cell_prefix();
+        let opcode = self.cell_opcode(0x89);
+        self.bytes(&[opcode, 0x03]);
+    }
+    /// Zero-extends the current cell into `rax`.
+    fn load_cell_to_rax(&mut self) {
+        match self.cell_size {
+            1 => self.bytes(&[0x0F, 0xB6, 0x03]),
+            2 => self.bytes(&[0x0F, 0xB7, 0x03]),
+            4 => self.bytes(&[0x8B, 0x03]),
+            _ => self.bytes(&[0x48, 0x8B, 0x03]),
+        }
+    }
+    /// `mov rax, imm64`
+    fn mov_rax(&mut self, value: u64) {
+        self.bytes(&[0x48, 0xB8]);
+        self.imm64(value);
+    }
+    /// Jumps to the tape error if `reg` (selected by the ModRM register bits) is outside the tape.
+    fn check_bounds(&mut self, modrm_reg: u8) {
+        // cmp reg, r12; jb TapeError
+        self.bytes(&[0x4C, 0x39, 0xE0 | modrm_reg, 0x0F, 0x82]);
+        self.rel32(Label::TapeError);
+        // cmp reg, r13; jae TapeError
+        self.bytes(&[0x4C, 0x39, 0xE8 | modrm_reg, 0x0F, 0x83]);
+        self.rel32(Label::TapeError);
+    }
+    /// `add rbx, amount` followed by a bounds check.
+    fn move_pointer(&mut self, amount: isize) {
+        let bytes = amount * self.cell_size as isize;
+        for part in split_i32(bytes) {
+            self.bytes(&[0x48, 0x81, 0xC3]);
+            self.imm32(part);
+        }
+        self.check_bounds(0x03);
+    }
+    fn write_syscall(&mut self, fd: i32, buffer_from_rbx: bool, len: i32) {
+        // mov eax, 1; mov edi, fd
+        self.bytes(&[0xB8]);
+        self.imm32(1);
+        self.bytes(&[0xBF]);
+        self.imm32(fd);
+        if buffer_from_rbx {
+            // mov rsi, rbx
+            self.bytes(&[0x48, 0x89, 0xDE]);
+        } else {
+            // mov rsi, r14
+            self.bytes(&[0x4C, 0x89, 0xF6]);
+        }
+        // mov edx, len; syscall
+        self.bytes(&[0xBA]);
+        self.imm32(len);
+        self.bytes(&[0x0F, 0x05]);
+    }
+    fn exit(&mut self, status: i32) {
+        // mov eax, 60; mov edi, status; syscall
+        self.bytes(&[0xB8]);
+        self.imm32(60);
+        self.bytes(&[0xBF]);
+        self.imm32(status);
+        self.bytes(&[0x0F, 0x05]);
+    }
+    fn fail(&mut self, message: Label, len: usize) {
+        // mov eax, 1; mov edi, 2; lea rsi, [rip + message]; mov edx, len; syscall
+        self.bytes(&[0xB8]);
+        self.imm32(1);
+        self.bytes(&[0xBF]);
+        self.imm32(2);
+        self.bytes(&[0x48, 0x8D, 0x35]);
+        self.rel32(message);
+        self.bytes(&[0xBA]);
+        self.imm32(len as i32);
+        self.bytes(&[0x0F, 0x05]);
+        self.exit(1);
+    }
+
+    fn resolve(&mut self) {
+        for &(position, label) in &self.fixups {
+            let target = self.labels[&label];
+            let relative = target as i64 - (position as i64 + 4);
+            self.code[position..position + 4].copy_from_slice(&(relative as i32).to_le_bytes());
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ImmediateTooLarge {
+    pub source_position: usize,
+}
+impl Display for ImmediateTooLarge {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "operand of the operation at position {} does not fit in an x86-64 immediate",
+            self.source_position
+        )
+    }
+}
+impl Error for ImmediateTooLarge {}
+
+pub fn generate(program: &Program, options: &CompileOptions) -> Result<Vec<u8>, ImmediateTooLarge> {
+    let cell_size: u8 = match options.cell {
+        CellKind::U8 => 1,
+        CellKind::U16 => 2,
+        CellKind::U32 => 4,
+        CellKind::U64 => 8,
+    };
+    let mut asm = Assembler {
+        code: Vec::new(),
+        cell_size,
+        labels: HashMap::new(),
+        fixups: Vec::new(),
+    };
+
+    // Prologue: the addresses are patched once the code size (and thus the tape address) is known.
+    // mov r12, tape; mov r13, tape end; mov r14, scratch; mov rbx, r12
+    asm.bytes(&[0x49, 0xBC]);
+    let tape_start_patch = asm.code.len();
+    asm.imm64(0);
+    asm.bytes(&[0x49, 0xBD]);
+    let tape_end_patch = asm.code.len();
+    asm.imm64(0);
+    asm.bytes(&[0x49, 0xBE]);
+    let scratch_patch = asm.code.len();
+    asm.imm64(0);
+    asm.bytes(&[0x4C, 0x89, 0xE3]);
+
+    let mut open_loops = Vec::new();
+    for (i, instruction) in program.instructions.iter().enumerate() {
+        let position = program.source[instruction.source_index].source_position;
+        match instruction.op {
+            Op::Add(amount) => {
+                asm.mov_rax(amount as i64 as u64);
+                asm.add_rax_to_cell(0x03);
+            }
+            Op::Move(amount) => asm.move_pointer(amount),
+            Op::Output => {
+                if cell_size == 1 {
+                    asm.write_syscall(1, true, 1);
+                } else {
+                    // Cells are written as big-endian bytes.
+                    asm.load_cell_to_rax();
+                    match cell_size {
+                        2 => asm.bytes(&[0x66, 0xC1, 0xC0, 0x08]), // rol ax, 8
+                        4 => asm.bytes(&[0x0F, 0xC8]),             // bswap eax
+                        _ => asm.bytes(&[0x48, 0x0F, 0xC8]),       // bswap rax
+                    }
+                    asm.bytes(&[0x49, 0x89, 0x06]); // mov [r14], rax
+                    asm.write_syscall(1, false, cell_size as i32);
+                }
+            }
+            Op::Input => {
+                // xor eax, eax; xor edi, edi; mov rsi, r14; mov edx, 1; syscall
+                asm.bytes(&[0x31, 0xC0, 0x31, 0xFF, 0x4C, 0x89, 0xF6, 0xBA]);
+                asm.imm32(1);
+                asm.bytes(&[0x0F, 0x05]);
+                // test rax, rax; jg read
+                asm.bytes(&[0x48, 0x85, 0xC0, 0x7F]);
+                let read_jump = asm.code.len();
+                asm.bytes(&[0x00]);
+                match options.eof {
+                    EofBehaviour::Zero => {
+                        asm.mov_rax(0);
+                        asm.store_rax_to_cell();
+                    }
+                    EofBehaviour::MinusOne => {
+                        asm.mov_rax(u64::MAX);
+                        asm.store_rax_to_cell();
+                    }
+                    EofBehaviour::Unchanged => {}
+                    EofBehaviour::Error => {
+                        asm.bytes(&[0xE9]);
+                        asm.rel32(Label::EofError);
+                    }
+                }
+                // jmp done
+                asm.bytes(&[0xEB]);
+                let done_jump = asm.code.len();
+                asm.bytes(&[0x00]);
+                asm.code[read_jump] = (asm.code.len() - read_jump - 1) as u8;
+                // movzx eax, byte [r14]
+                asm.bytes(&[0x41, 0x0F, 0xB6, 0x06]);
+                asm.store_rax_to_cell();
+                asm.code[done_jump] = (asm.code.len() - done_jump - 1) as u8;
+            }
+            Op::JumpIfZero(_) => {
+                open_loops.push(i);
+                asm.compare_cell_to_zero();
+                // je LoopEnd
+                asm.bytes(&[0x0F, 0x84]);
+                asm.rel32(Label::LoopEnd(i));
+                asm.label(Label::LoopBody(i));
+            }
+            Op::JumpIfNonzero(_) => {
+                let open = open_loops.pop().expect("loops should be balanced");
+                asm.compare_cell_to_zero();
+                // jne LoopBody
+                asm.bytes(&[0x0F, 0x85]);
+                asm.rel32(Label::LoopBody(open));
+                asm.label(Label::LoopEnd(open));
+            }
+            Op::Clear => {
+                asm.mov_rax(0);
+                asm.store_rax_to_cell();
+            }
+            Op::MulAdd { offset, factor } => {
+                asm.compare_cell_to_zero();
+                // je skip
+                asm.bytes(&[0x74]);
+                let skip_jump = asm.code.len();
+                asm.bytes(&[0x00]);
+                asm.load_cell_to_rax();
+                // imul rax, rax, factor
+                asm.bytes(&[0x48, 0x69, 0xC0]);
+                asm.imm32(narrow(factor, position)?);
+                // lea rcx, [rbx + offset]
+                asm.bytes(&[0x48, 0x8D, 0x8B]);
+                let offset = offset.checked_mul(cell_size as isize).unwrap_or(isize::MAX);
+                asm.imm32(narrow(offset, position)?);
+                asm.check_bounds(0x01);
+                asm.add_rax_to_cell(0x01);
+                asm.code[skip_jump] = (asm.code.len() - skip_jump - 1) as u8;
+            }
+            Op::Scan(step) => {
+                let start = asm.code.len();
+                asm.compare_cell_to_zero();
+                // je done (patched below)
+                asm.bytes(&[0x0F, 0x84]);
+                let done_jump = asm.code.len();
+                asm.imm32(0);
+                asm.move_pointer(step);
+                // jmp start
+                asm.bytes(&[0xE9]);
+                let back = start as i64 - (asm.code.len() as i64 + 4);
+                asm.imm32(back as i32);
+                let forward = asm.code.len() - (done_jump + 4);
+                asm.code[done_jump..done_jump + 4].copy_from_slice(&(forward as i32).to_le_bytes());
+            }
+        }
+    }
+    asm.exit(0);
+
+    asm.label(Label::TapeError);
+    asm.fail(Label::TapeErrorMessage, TAPE_ERROR.len());
+    asm.label(Label::EofError);
+    asm.fail(Label::EofErrorMessage, EOF_ERROR.len());
+    asm.label(Label::TapeErrorMessage);
+    asm.bytes(TAPE_ERROR);
+    asm.label(Label::EofErrorMessage);
+    asm.bytes(EOF_ERROR);
+    asm.resolve();
+
+    let file_size = (HEADERS_SIZE + asm.code.len()) as u64;
+    let scratch_address = (BASE_ADDRESS + file_size).next_multiple_of(PAGE_SIZE) + PAGE_SIZE;
+    let tape_address = scratch_address + SCRATCH_SIZE;
+    let tape_end = tape_address + options.tape_size as u64 * cell_size as u64;
+    asm.code[tape_start_patch..tape_start_patch + 8].copy_from_slice(&tape_address.to_le_bytes());
+    asm.code[tape_end_patch..tape_end_patch + 8].copy_from_slice(&tape_end.to_le_bytes());
+    asm.code[scratch_patch..scratch_patch + 8].copy_from_slice(&scratch_address.to_le_bytes());
+
+    let mut out = Vec::with_capacity(file_size as usize);
+    // ELF header
+    out.extend_from_slice(&[0x7F, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    out.extend_from_slice(&2u16.to_le_bytes()); // e_type: executable
+    out.extend_from_slice(&0x3Eu16.to_le_bytes()); // e_machine: x86-64
+    out.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    out.extend_from_slice(&(BASE_ADDRESS + HEADERS_SIZE as u64).to_le_bytes()); // e_entry
+    out.extend_from_slice(&(ELF_HEADER_SIZE as u64).to_le_bytes()); // e_phoff
+    out.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+    out.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    out.extend_from_slice(&(ELF_HEADER_SIZE as u16).to_le_bytes()); // e_ehsize
+    out.extend_from_slice(&(PROGRAM_HEADER_SIZE as u16).to_le_bytes()); // e_phentsize
+    out.extend_from_slice(&2u16.to_le_bytes()); // e_phnum
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shentsize
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shnum
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shstrndx
+
+    // Code segment (read + execute), containing the whole file
+    program_header(&mut out, 0b101, 0, BASE_ADDRESS, file_size, file_size);
+    // Tape segment (read + write), zero-initialised
+    let memory_size = tape_end - scratch_address;
+    program_header(&mut out, 0b110, 0, scratch_address, 0, memory_size);
+
+    out.extend_from_slice(&asm.code);
+    Ok(out)
+}
+
+fn program_header(
+    out: &mut Vec<u8>,
+    flags: u32,
+    offset: u64,
+    address: u64,
+    file_size: u64,
+    memory_size: u64,
+) {
+    out.extend_from_slice(&1u32.to_le_bytes()); // p_type: PT_LOAD
+    out.extend_from_slice(&flags.to_le_bytes());
+    out.extend_from_slice(&offset.to_le_bytes());
+    out.extend_from_slice(&address.to_le_bytes()); // p_vaddr
+    out.extend_from_slice(&address.to_le_bytes()); // p_paddr
+    out.extend_from_slice(&file_size.to_le_bytes());
+    out.extend_from_slice(&memory_size.to_le_bytes());
+    out.extend_from_slice(&PAGE_SIZE.to_le_bytes()); // p_align
+}
+
+fn narrow(value: isize, source_position: usize) -> Result<i32, ImmediateTooLarge> {
+    i32::try_from(value).map_err(|_| ImmediateTooLarge { source_position })
+}
+
+/// Splits an amount into parts that fit in 32-bit immediates.
+fn split_i32(mut amount: isize) -> Vec<i32> {
+    let mut parts = Vec::new();
+    while amount != 0 {
+        let part = amount.clamp(i32::MIN as isize, i32::MAX as isize);
+        parts.push(part as i32);
+        amount -= part;
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::OptimisedInstruction;
+    use crate::{Instruction, LoadedInstruction};
+
+    fn generate_op(op: Op) -> Result<Vec<u8>, ImmediateTooLarge> {
+        let program = Program {
+            instructions: vec![OptimisedInstruction {
+                op,
+                source_index: 0,
+            }],
+            source: vec![LoadedInstruction {
+                instruction: Instruction::IncrementData,
+                source_position: 3,
+                partner: None,
+                follows_hash: false,
+            }],
+        };
+        generate(&program, &CompileOptions::default())
+    }
+
+    #[test]
+    fn large_immediates_are_errors() {
+        let too_large = i32::MAX as isize + 1;
+        for op in [
+            Op::MulAdd {
+                offset: 1,
+                factor: too_large,
+            },
+            Op::MulAdd {
+                offset: too_large,
+                factor: 1,
+            },
+            Op::MulAdd {
+                offset: isize::MIN,
+                factor: 1,
+            },
+        ] {
+            let error = generate_op(op).expect_err("the operand doesn't fit");
+            assert_eq!(error.source_position, 3);
+        }
+        assert!(generate_op(Op::Move(too_large)).is_ok());
+    }
+}
@@ -0,0 +1,128 @@
+//! Generates Rust source code from a script.
+use crate::compile::{nested_ops, CompileOptions};
+use crate::ir::{Op, Program};
+use crate::EofBehaviour;
+use std::fmt::Write;
+
+/// Generates a module with a `run` function, and a `main` function if `with_main` is set.
+pub fn generate(program: &Program, options: &CompileOptions, with_main: bool) -> String {
+    let on_eof = match options.eof {
+        EofBehaviour::Zero => "*cell = 0;",
+        EofBehaviour::MinusOne => "*cell = offset_cell(0, -1);",
+        EofBehaviour::Unchanged => "",
+        EofBehaviour::Error => {
+            r#"return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "read past the end of input"));"#
+        }
+    };
+
+    let mut out = String::new();
+    // Writing to a String cannot fail, so the results are ignored throughout.
+    let _ = write!(
+        out,
+        r#"// Generated by brainfuck.
+#![allow(clippy::all)]
+#![allow(unused, unreachable_code)]
+
+use std::io::{{self, Read, Write}};
+
+pub type Cell = {cell};
+pub const MIN_CELL_VALUE: Cell = Cell::MIN;
+pub const MAX_CELL_VALUE: Cell = Cell::MAX;
+pub const TAPE_SIZE: usize = {tape_size};
+
+/// Adds `amount` to a cell value, wrapping around between the cell bounds.
+fn offset_cell(value: Cell, amount: i128) -> Cell {{
+    if MIN_CELL_VALUE == Cell::MIN && MAX_CELL_VALUE == Cell::MAX {{
+        return value.wrapping_add(amount as Cell);
+    }}
+    let (min, max) = (MIN_CELL_VALUE as i128, MAX_CELL_VALUE as i128);
+    let range = max - min + 1;
+    ((value as i128 - min + amount.rem_euclid(range)).rem_euclid(range) + min) as Cell
+}}
+
+fn move_pointer(pointer: usize, amount: isize) -> io::Result<usize> {{
+    match pointer.checked_add_signed(amount) {{
+        Some(pointer) if pointer < TAPE_SIZE => Ok(pointer),
+        _ => Err(io::Error::new(
+            io::ErrorKind::Other,
+            "data pointer moved outside of the tape",
+        )),
+    }}
+}}
+
+fn read_cell(input: &mut impl Read, cell: &mut Cell) -> io::Result<()> {{
+    let mut buf = [0u8];
+    loop {{
+        match input.read(&mut buf) {{
+            Ok(0) => {{
+                {on_eof}
+                return Ok(());
+            }}
+            Ok(_) => {{
+                *cell = buf[0] as Cell;
+                return Ok(());
+            }}
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }}
+    }}
+}}
+
+/// Runs the script, reading from `input` and writing to `output`.
+pub fn run(input: &mut impl Read, output: &mut impl Write) -> io::Result<()> {{
+    let mut tape = vec![0 as Cell; TAPE_SIZE];
+    let mut p = 0usize;
+"#,
+        cell = options.cell,
+        tape_size = options.tape_size,
+    );
+
+    for (depth, op) in nested_ops(program) {
+        let indent = "    ".repeat(depth + 1);
+        let _ = match op {
+            Op::Add(amount) => writeln!(out, "{indent}tape[p] = offset_cell(tape[p], {amount});"),
+            Op::Move(amount) => writeln!(out, "{indent}p = move_pointer(p, {amount})?;"),
+            Op::Output => writeln!(out, "{indent}output.write_all(&tape[p].to_be_bytes())?;"),
+            Op::Input => writeln!(
+                out,
+                "{indent}output.flush()?;\n{indent}read_cell(input, &mut tape[p])?;"
+            ),
+            Op::JumpIfZero(_) => writeln!(out, "{indent}while tape[p] != 0 {{"),
+            Op::JumpIfNonzero(_) => writeln!(out, "{indent}}}"),
+            Op::Clear => writeln!(out, "{indent}tape[p] = 0;"),
+            Op::MulAdd { offset, factor } => writeln!(
+                out,
+                "{indent}if tape[p] != 0 {{\n\
+                 {indent}    let target = move_pointer(p, {offset})?;\n\
+                 {indent}    tape[target] = offset_cell(tape[target], tape[p] as i128 * {factor});\n\
+                 {indent}}}"
+            ),
+            Op::Scan(step) => writeln!(
+                out,
+                "{indent}while tape[p] != 0 {{\n{indent}    p = move_pointer(p, {step})?;\n{indent}}}"
+            ),
+        };
+    }
+
+    out.push_str(
+        r#"    output.flush()
+}
+"#,
+    );
+
+    if with_main {
+        out.push_str(
+            r#"
+fn main() {
+    let mut output = io::BufWriter::new(io::stdout().lock());
+    if let Err(e) = run(&mut io::stdin().lock(), &mut output) {
+        let _ = output.flush();
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+"#,
+        );
+    }
+    out
+}
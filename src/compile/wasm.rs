@@ -0,0 +1,383 @@
+//! Generates WebAssembly modules from a script, either as a binary `.wasm` or as `.wat` text.
+//!
+//! The module imports `env.read` and `env.write` for I/O, and exports `run` and the `memory`
+//! holding the tape.
+use crate::compile::CompileOptions;
+use crate::ir::{Op, Program};
+use crate::CellKind;
+use std::fmt::Write;
+
+const PAGE_SIZE: usize = 0x10000;
+
+/// Local holding the address of the current cell.
+const POINTER: u32 = 0;
+/// Local holding the address of the target cell of a multiplication.
+const TARGET: u32 = 1;
+
+const READ_FUNCTION: u32 = 0;
+const WRITE_FUNCTION: u32 = 1;
+const RUN_FUNCTION: u32 = 2;
+
+/// The instructions used by generated code.
+/// The `Cell*` instructions operate on the value type that holds cells.
+#[derive(Copy, Clone)]
+enum Instr {
+    Unreachable,
+    Block,
+    Loop,
+    If,
+    End,
+    Br(u32),
+    BrIf(u32),
+    Call(u32),
+    LocalGet(u32),
+    LocalSet(u32),
+    I32Const(i32),
+    I32Add,
+    I32GeU,
+    I32Eqz,
+    CellLoad,
+    CellStore,
+    CellConst(i64),
+    CellAdd,
+    CellMul,
+    CellEqz,
+}
+
+/// Generates a binary module.
+pub fn generate_binary(program: &Program, options: &CompileOptions) -> Vec<u8> {
+    let cell_type = value_type(options.cell);
+    let mut module = b"\0asm".to_vec();
+    module.extend_from_slice(&1u32.to_le_bytes());
+
+    // Types: read, write and run, in that order.
+    section(&mut module, 1, |s| {
+        uleb(s, 3);
+        s.extend_from_slice(&[0x60, 0, 1, cell_type]);
+        s.extend_from_slice(&[0x60, 1, cell_type, 0]);
+        s.extend_from_slice(&[0x60, 0, 0]);
+    });
+    section(&mut module, 2, |s| {
+        uleb(s, 2);
+        for (name, type_index) in [("read", 0), ("write", 1)] {
+            name_bytes(s, "env");
+            name_bytes(s, name);
+            s.push(0x00);
+            uleb(s, type_index);
+        }
+    });
+    section(&mut module, 3, |s| {
+        uleb(s, 1);
+        uleb(s, 2);
+    });
+    section(&mut module, 5, |s| {
+        uleb(s, 1);
+        s.push(0x00);
+        uleb(s, memory_pages(options) as u64);
+    });
+    section(&mut module, 7, |s| {
+        uleb(s, 2);
+        name_bytes(s, "run");
+        s.push(0x00);
+        uleb(s, RUN_FUNCTION as u64);
+        name_bytes(s, "memory");
+        s.push(0x02);
+        uleb(s, 0);
+    });
+    section(&mut module, 10, |s| {
+        uleb(s, 1);
+        let mut body = Vec::new();
+        // Two i32 locals: the pointer and the multiplication target.
+        body.extend_from_slice(&[1, 2, 0x7f]);
+        for instr in instructions(program, options) {
+            encode(&mut body, instr, options.cell);
+        }
+        body.push(0x0b);
+        uleb(s, body.len() as u64);
+        s.extend_from_slice(&body);
+    });
+    module
+}
+
+/// Generates a module in the text format.
+pub fn generate_text(program: &Program, options: &CompileOptions) -> String {
+    let cell_type = match options.cell {
+        CellKind::U64 => "i64",
+        _ => "i32",
+    };
+    let mut out = String::new();
+    // Writing to a String cannot fail, so the results are ignored throughout.
+    let _ = write!(
+        out,
+        r#";; Generated by brainfuck.
+(module
+  (import "env" "read" (func $read (result {cell_type})))
+  (import "env" "write" (func $write (param {cell_type})))
+  (memory (export "memory") {pages})
+  (func (export "run")
+    (local $p i32) (local $target i32)
+"#,
+        pages = memory_pages(options),
+    );
+    let mut depth = 2;
+    for instr in instructions(program, options) {
+        if let Instr::End = instr {
+            depth -= 1;
+        }
+        let _ = writeln!(
+            out,
+            "{}{}",
+            "  ".repeat(depth),
+            text(instr, options.cell, cell_type)
+        );
+        if let Instr::Block | Instr::Loop | Instr::If = instr {
+            depth += 1;
+        }
+    }
+    out.push_str("  )\n)\n");
+    out
+}
+
+fn memory_pages(options: &CompileOptions) -> usize {
+    (tape_bytes(options).div_ceil(PAGE_SIZE)).max(1)
+}
+fn tape_bytes(options: &CompileOptions) -> usize {
+    options.tape_size * cell_size(options.cell)
+}
+fn cell_size(cell: CellKind) -> usize {
+    match cell {
+        CellKind::U8 => 1,
+        CellKind::U16 => 2,
+        CellKind::U32 => 4,
+        CellKind::U64 => 8,
+    }
+}
+fn value_type(cell: CellKind) -> u8 {
+    match cell {
+        CellKind::U64 => 0x7e,
+        _ => 0x7f,
+    }
+}
+
+/// Lowers the program to a flat list of instructions.
+/// The pointer is kept as a byte address into memory, so cells can be accessed directly.
+fn instructions(program: &Program, options: &CompileOptions) -> Vec<Instr> {
+    use Instr::*;
+    let size = cell_size(options.cell) as i64;
+    let limit = tape_bytes(options);
+    // Adds `amount` cells to the address in `local`, trapping if it leaves the tape.
+    let offset = |code: &mut Vec<Instr>, local: u32, amount: isize| {
+        let bytes = i32::try_from(amount as i64 * size);
+        match bytes {
+            Ok(bytes) if (bytes.unsigned_abs() as usize) < limit => {
+                code.extend([LocalGet(POINTER), I32Const(bytes), I32Add, LocalSet(local)]);
+                code.extend([LocalGet(local), I32Const(limit as i32), I32GeU]);
+                code.extend([If, Unreachable, End]);
+            }
+            _ => code.push(Unreachable),
+        }
+    };
+
+    let mut code = Vec::new();
+    for instruction in &program.instructions {
+        match instruction.op {
+            Op::Add(amount) => code.extend([
+                LocalGet(POINTER),
+                LocalGet(POINTER),
+                CellLoad,
+                CellConst(amount as i64),
+                CellAdd,
+                CellStore,
+            ]),
+            Op::Move(amount) => offset(&mut code, POINTER, amount),
+            Op::Output => code.extend([LocalGet(POINTER), CellLoad, Call(WRITE_FUNCTION)]),
+            Op::Input => code.extend([LocalGet(POINTER), Call(READ_FUNCTION), CellStore]),
+            Op::JumpIfZero(_) => {
+                code.extend([Block, LocalGet(POINTER), CellLoad, CellEqz, BrIf(0), Loop])
+            }
+            Op::JumpIfNonzero(_) => code.extend([
+                LocalGet(POINTER),
+                CellLoad,
+                CellEqz,
+                I32Eqz,
+                BrIf(0),
+                End,
+                End,
+            ]),
+            Op::Clear => code.extend([LocalGet(POINTER), CellConst(0), CellStore]),
+            Op::MulAdd {
+                offset: cells,
+                factor,
+            } => {
+                code.extend([Block, LocalGet(POINTER), CellLoad, CellEqz, BrIf(0)]);
+                offset(&mut code, TARGET, cells);
+                code.extend([
+                    LocalGet(TARGET),
+                    LocalGet(TARGET),
+                    CellLoad,
+                    LocalGet(POINTER),
+                    CellLoad,
+                    CellConst(factor as i64),
+                    CellMul,
+                    CellAdd,
+                    CellStore,
+                    End,
+                ]);
+            }
+            Op::Scan(step) => {
+                code.extend([Block, Loop, LocalGet(POINTER), CellLoad, CellEqz, BrIf(1)]);
+                offset(&mut code, POINTER, step);
+                code.extend([Br(0), End, End]);
+            }
+        }
+    }
+    code
+}
+
+fn encode(out: &mut Vec<u8>, instr: Instr, cell: CellKind) {
+    let wide = cell == CellKind::U64;
+    // Memory arguments are the alignment as a power of two, then the offset.
+    let memarg = [cell_size(cell).trailing_zeros() as u8, 0];
+    match instr {
+        Instr::Unreachable => out.push(0x00),
+        Instr::Block => out.extend_from_slice(&[0x02, 0x40]),
+        Instr::Loop => out.extend_from_slice(&[0x03, 0x40]),
+        Instr::If => out.extend_from_slice(&[0x04, 0x40]),
+        Instr::End => out.push(0x0b),
+        Instr::Br(depth) => {
+            out.push(0x0c);
+            uleb(out, depth as u64);
+        }
+        Instr::BrIf(depth) => {
+            out.push(0x0d);
+            uleb(out, depth as u64);
+        }
+        Instr::Call(function) => {
+            out.push(0x10);
+            uleb(out, function as u64);
+        }
+        Instr::LocalGet(local) => {
+            out.push(0x20);
+            uleb(out, local as u64);
+        }
+        Instr::LocalSet(local) => {
+            out.push(0x21);
+            uleb(out, local as u64);
+        }
+        Instr::I32Const(value) => {
+            out.push(0x41);
+            sleb(out, value as i64);
+        }
+        Instr::I32Add => out.push(0x6a),
+        Instr::I32GeU => out.push(0x4f),
+        Instr::I32Eqz => out.push(0x45),
+        Instr::CellLoad => {
+            out.push(match cell {
+                CellKind::U8 => 0x2d,
+                CellKind::U16 => 0x2f,
+                CellKind::U32 => 0x28,
+                CellKind::U64 => 0x29,
+            });
+            out.extend_from_slice(&memarg);
+        }
+        Instr::CellStore => {
+            out.push(match cell {
+                CellKind::U8 => 0x3a,
+                CellKind::U16 => 0x3b,
+                CellKind::U32 => 0x36,
+                CellKind::U64 => 0x37,
+            });
+            out.extend_from_slice(&memarg);
+        }
+        Instr::CellConst(value) if wide => {
+            out.push(0x42);
+            sleb(out, value);
+        }
+        Instr::CellConst(value) => {
+            out.push(0x41);
+            sleb(out, value as i32 as i64);
+        }
+        Instr::CellAdd => out.push(if wide { 0x7c } else { 0x6a }),
+        Instr::CellMul => out.push(if wide { 0x7e } else { 0x6c }),
+        Instr::CellEqz => out.push(if wide { 0x50 } else { 0x45 }),
+    }
+}
+
+fn text(instr: Instr, cell: CellKind, cell_type: &str) -> String {
+    let function_name = |function| match function {
+        READ_FUNCTION => "$read",
+        WRITE_FUNCTION => "$write",
+        _ => "$run",
+    };
+    let local_name = |local| if local == POINTER { "$p" } else { "$target" };
+    match instr {
+        Instr::Unreachable => "unreachable".to_string(),
+        Instr::Block => "block".to_string(),
+        Instr::Loop => "loop".to_string(),
+        Instr::If => "if".to_string(),
+        Instr::End => "end".to_string(),
+        Instr::Br(depth) => format!("br {}", depth),
+        Instr::BrIf(depth) => format!("br_if {}", depth),
+        Instr::Call(function) => format!("call {}", function_name(function)),
+        Instr::LocalGet(local) => format!("local.get {}", local_name(local)),
+        Instr::LocalSet(local) => format!("local.set {}", local_name(local)),
+        Instr::I32Const(value) => format!("i32.const {}", value),
+        Instr::I32Add => "i32.add".to_string(),
+        Instr::I32GeU => "i32.ge_u".to_string(),
+        Instr::I32Eqz => "i32.eqz".to_string(),
+        Instr::CellLoad => match cell {
+            CellKind::U8 => "i32.load8_u".to_string(),
+            CellKind::U16 => "i32.load16_u".to_string(),
+            _ => format!("{}.load", cell_type),
+        },
+        Instr::CellStore => match cell {
+            CellKind::U8 => "i32.store8".to_string(),
+            CellKind::U16 => "i32.store16".to_string(),
+            _ => format!("{}.store", cell_type),
+        },
+        Instr::CellConst(value) if cell == CellKind::U64 => format!("i64.const {}", value),
+        Instr::CellConst(value) => format!("i32.const {}", value as i32),
+        Instr::CellAdd => format!("{}.add", cell_type),
+        Instr::CellMul => format!("{}.mul", cell_type),
+        Instr::CellEqz => format!("{}.eqz", cell_type),
+    }
+}
+
+/// Appends a section with the contents written by `contents`.
+fn section(out: &mut Vec<u8>, id: u8, contents: impl FnOnce(&mut Vec<u8>)) {
+    let mut section = Vec::new();
+    contents(&mut section);
+    out.push(id);
+    uleb(out, section.len() as u64);
+    out.extend_from_slice(&section);
+}
+
+fn name_bytes(out: &mut Vec<u8>, name: &str) {
+    uleb(out, name.len() as u64);
+    out.extend_from_slice(name.as_bytes());
+}
+
+fn uleb(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn sleb(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
@@ -1,14 +1,20 @@
 mod command;
 mod command_input;
+mod history;
+mod input;
 mod runtime_data;
 mod simple_text_block;
+mod snippet;
 mod source_code;
 
-use crate::interactive::runtime_data::RuntimeDataWidget;
+use crate::interactive::runtime_data::{CellFormat, RuntimeDataWidget};
 use crate::interactive::simple_text_block::SimpleTextBlock;
-use crate::{Instruction, LoadedInstruction, RuntimeContext, RuntimeContextU64, Script};
+use brainfuck::{
+    Breakpoints, ExecutionObserver, Instruction, LoadedInstruction, RuntimeContext, Script,
+    Snapshot,
+};
+use crossterm::event;
 use crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers};
-use crossterm::{event, execute};
 use ratatui::layout::Constraint::{Length, Max, Min};
 use ratatui::prelude::*;
 use ratatui::widgets::{Block, Borders, Padding, Paragraph};
@@ -17,7 +23,6 @@ use std::borrow::Cow;
 use std::fmt::Display;
 use std::io;
 use std::io::{Cursor, Read};
-use std::ops::Div;
 use std::rc::Rc;
 use std::time::{Duration, Instant};
 use tui_input::backend::crossterm::EventHandler;
@@ -29,6 +34,17 @@ macro_rules! block_widget {
                 self.$block = self.$block.title(title);
                 self
             }
+            #[allow(dead_code)]
+            pub fn border_style(mut self, style: ratatui::style::Style) -> Self {
+                self.$block = self.$block.border_style(style);
+                self
+            }
+            #[allow(dead_code)]
+            pub fn title_style(mut self, style: ratatui::style::Style) -> Self {
+                self.$block = self.$block.title_style(style);
+                self
+            }
+            #[allow(dead_code)]
             pub fn title_alignment(mut self, alignment: ratatui::layout::Alignment) -> Self {
                 self.$block = self.$block.title_alignment(alignment);
                 self
@@ -64,15 +80,21 @@ macro_rules! widget_setter {
 }
 use crate::interactive::command::{parse_command, Command, CommandPartState, CommandResult};
 use crate::interactive::command_input::{CommandInput, CommandInputState, OwnedCommandResult};
+use crate::interactive::history::{Change, History};
+use crate::interactive::input::{parse_escapes, InputBufferWidget};
 use crate::interactive::source_code::SourceCode;
-pub(self) use {block_widget, widget_setter};
+use {block_widget, widget_setter};
 
-type Cell = u64;
+type Cell = i64;
+
+/// Longest time spent executing instructions between two frames.
+const FRAME_EXECUTION_TIME: Duration = Duration::from_millis(15);
 
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub enum Activity {
     Normal,
     Command,
+    Input,
 }
 
 pub struct InteractiveState {
@@ -84,6 +106,18 @@ pub struct InteractiveState {
     pub frame_count: u128,
     pub script: Script,
     pub runtime_context: RuntimeContext<Cell>,
+    pub io: Rc<InteractiveIo>,
+    /// Changes made by executing instructions, for stepping backwards.
+    pub history: History,
+    pub breakpoints: Breakpoints<Cell>,
+    pub cell_format: CellFormat,
+    /// The cell changed by the last instruction, if it changed one.
+    pub changed_cell: Rc<std::cell::Cell<Option<usize>>>,
+    /// Input being typed into the input pane.
+    pub input_line: tui_input::Input,
+    /// Whether the user ended the input, so that reads past the queued input get the end of
+    /// input instead of waiting for more.
+    pub input_closed: bool,
 
     pub activity: Activity,
     pub command_input: CommandInputState<Cell>,
@@ -108,8 +142,45 @@ impl InteractiveState {
     }
 }
 impl InteractiveState {
+    fn new(script: Script) -> Self {
+        let io = Rc::new(InteractiveIo::default());
+        let changed_cell = Rc::new(std::cell::Cell::new(None));
+
+        Self {
+            should_quit: false,
+            execution_paused: true,
+            execution_clock_speed: Duration::from_millis(100),
+            last_cycle_time: Instant::now(),
+            last_executed_instruction: None,
+            frame_count: 0,
+            script,
+            runtime_context: new_runtime_context(&io, &changed_cell),
+            io,
+            history: History::default(),
+            breakpoints: Breakpoints::default(),
+            cell_format: CellFormat::default(),
+            changed_cell,
+            input_line: tui_input::Input::default(),
+            input_closed: false,
+            activity: Activity::Normal,
+            command_input: CommandInputState::default(),
+            command_output: Vec::new(),
+        }
+    }
+    /// Whether the next instruction reads input that hasn't been queued yet.
+    pub fn awaiting_input(&self) -> bool {
+        !self.input_closed
+            && self.script.instruction() == Some(Instruction::AcceptData)
+            && self.io.pending_input() == 0
+    }
+    /// Adds `bytes` to the end of the input, which also opens the input again if it was ended.
+    fn queue_input(&mut self, bytes: &[u8]) {
+        self.io.input.lock().get_mut().extend_from_slice(bytes);
+        self.input_closed = false;
+    }
+
     fn execute(&mut self) {
-        if !self.script.has_remaining_instructions() {
+        if !self.script.has_remaining_instructions() || self.awaiting_input() {
             return;
         }
         self.last_executed_instruction = self
@@ -117,74 +188,159 @@ impl InteractiveState {
             .instructions
             .get(self.script.instruction_pointer)
             .cloned();
-        self.script.execute_instruction(&mut self.runtime_context);
+        let change = Change::before_step(self);
+        self.changed_cell.set(None);
+        if let Err(e) = self.script.execute_instruction(&mut self.runtime_context) {
+            let (line, column) = self.script.line_column(e.instruction.source_position);
+            let cycles = self.script.cycles;
+            self.execution_paused = true;
+            self.cmd_error(format_args!(
+                "runtime error at line {}, column {} after {} cycles: {}",
+                line, column, cycles, e.kind
+            ));
+        } else {
+            self.history.record(change);
+        }
+    }
+
+    /// Empties the input and output, and opens the input again if it was ended.
+    fn clear_io(&mut self) {
+        *self.io.input.lock() = Cursor::default();
+        self.io.output.write().clear();
+        self.runtime_context.output_len = 0;
+        self.input_closed = false;
+    }
+
+    /// Goes back to the start of the script with an empty tape, so that it runs again with the
+    /// same input. The settings of the runtime context are kept.
+    fn restart(&mut self) {
+        let context = &mut self.runtime_context;
+        context.data.clear();
+        context.data_pointer = 0;
+        context.origin = 0;
+        context.output_len = 0;
+        context.started = Instant::now();
+        self.io.input.lock().set_position(0);
+        self.io.output.write().clear();
+        self.script.instruction_pointer = 0;
+        self.script.cycles = 0;
+        self.last_executed_instruction = None;
+        self.changed_cell.set(None);
+        self.history.clear();
+        self.execution_paused = true;
+    }
+
+    /// Executes the instructions that are due at the current speed.
+    /// Speeds faster than the frame rate run several instructions per frame, for up to
+    /// [`FRAME_EXECUTION_TIME`].
+    fn run_due_instructions(&mut self) {
+        let started = Instant::now();
+        while !self.execution_paused
+            && self.script.has_remaining_instructions()
+            && !self.awaiting_input()
+        {
+            if self.last_cycle_time.elapsed() < self.execution_clock_speed {
+                return;
+            }
+            self.execute();
+            self.check_breakpoints();
+            self.last_cycle_time += self.execution_clock_speed;
+            if started.elapsed() > FRAME_EXECUTION_TIME {
+                // Falling this far behind resets the clock, rather than catching up later.
+                self.last_cycle_time = Instant::now();
+                return;
+            }
+        }
+        // Starting again shouldn't run all the instructions that would have been due meanwhile.
         self.last_cycle_time = Instant::now();
     }
 }
+impl InteractiveState {
+    /// Pauses execution if the script got to a breakpoint.
+    /// Only running scripts stop, so starting again always gets past the breakpoint.
+    fn check_breakpoints(&mut self) {
+        if self.execution_paused {
+            return;
+        }
+        let reason = self.breakpoints.check(
+            &self.script,
+            &self.runtime_context,
+            self.last_executed_instruction,
+        );
+        if let Some(reason) = reason {
+            self.execution_paused = true;
+            self.cmd_info(format_args!("Stopped at {}", reason));
+        }
+    }
+}
+/// Keeps track of the cell changed by the last instruction, for highlighting it.
+struct ChangedCellObserver(Rc<std::cell::Cell<Option<usize>>>);
+impl ExecutionObserver<Cell> for ChangedCellObserver {
+    fn cell_changed(&mut self, index: usize, _old: &Cell, _new: &Cell) {
+        self.0.set(Some(index));
+    }
+}
+
 #[derive(Default)]
-struct InteractiveIo {
+pub struct InteractiveIo {
     input: Mutex<Cursor<Vec<u8>>>,
     output: RwLock<Vec<u8>>,
 }
+impl InteractiveIo {
+    /// Number of queued bytes the script hasn't read yet.
+    pub fn pending_input(&self) -> usize {
+        let input = self.input.lock();
+        input
+            .get_ref()
+            .len()
+            .saturating_sub(input.position() as usize)
+    }
+}
 
-pub fn interactive_runtime<B: Backend>(
-    terminal: &mut Terminal<B>,
-    mut rt: Script,
-) -> io::Result<()> {
-    let mut io = Rc::new(InteractiveIo::default());
-
-    let mut state = InteractiveState {
-        should_quit: false,
-        execution_paused: true,
-        execution_clock_speed: Duration::from_millis(100),
-        last_cycle_time: Instant::now(),
-        last_executed_instruction: None,
-        frame_count: 0,
-        script: rt,
-        runtime_context: RuntimeContext::new(
-            {
-                let io = io.clone();
-                move || {
-                    let mut buf = [0u8];
-                    io.input
-                        .lock()
-                        .read_exact(&mut buf)
-                        .expect("failed to read");
-                    buf[0] as u64
-                }
-            },
-            {
-                let io = io.clone();
-                move |value| {
-                    io.output.write().push(value as u8);
-                }
-            },
-        ),
-        activity: Activity::Normal,
-        command_input: CommandInputState::default(),
-        command_output: Vec::new(),
-    };
+/// Makes the runtime context interactive mode starts with, reading from and writing to `io`.
+fn new_runtime_context(
+    io: &Rc<InteractiveIo>,
+    changed_cell: &Rc<std::cell::Cell<Option<usize>>>,
+) -> RuntimeContext<Cell> {
+    // Cells are i64 for extended customisation,
+    // so they're bounded like a standard Brainfuck environment to start with.
+    RuntimeContext::builder()
+        .read_with({
+            let io = io.clone();
+            move || {
+                let mut buf = [0u8];
+                let read = io.input.lock().read(&mut buf)?;
+                Ok((read == 1).then_some(buf[0] as Cell))
+            }
+        })
+        .write_with({
+            let io = io.clone();
+            move |value| {
+                io.output.write().push(value as u8);
+                Ok(())
+            }
+        })
+        .cell_bounds(0, u8::MAX as Cell)
+        .observer(ChangedCellObserver(changed_cell.clone()))
+        .build()
+}
 
-    // Since we use RuntimeContext<i128> for extended customisation,
-    // we have to set these default values manually.
-    // For executing code in a standard Brainfuck environment, just using RuntimeContext<u8> is fine.
-    state.runtime_context.min_cell_value = 0;
-    state.runtime_context.max_cell_value = u8::MAX as Cell;
+pub fn interactive_runtime<B: Backend>(terminal: &mut Terminal<B>, rt: Script) -> io::Result<()> {
+    let mut state = InteractiveState::new(rt);
+    let io = state.io.clone();
 
     loop {
-        let completed = terminal.draw(|frame| ui(frame, &mut state, &io));
+        terminal.draw(|frame| ui(frame, &mut state, &io))?;
         if event::poll(Duration::from_millis(20))? {
             let event = event::read()?;
 
             match state.activity {
                 Activity::Normal => handle_event_normal(event, &mut state),
                 Activity::Command => handle_event_command(event, &mut state),
+                Activity::Input => handle_event_input(event, &mut state),
             }
         }
-        if !state.execution_paused && state.last_cycle_time.elapsed() > state.execution_clock_speed
-        {
-            state.execute();
-        }
+        state.run_due_instructions();
         if state.should_quit {
             return Ok(());
         }
@@ -200,13 +356,33 @@ fn handle_event_normal(event: Event, state: &mut InteractiveState) {
                     'q' => {
                         state.should_quit = true;
                     }
-                    'n' if keydown => state.execute(),
+                    'n' if keydown => {
+                        if state.awaiting_input() {
+                            state.cmd_error("the script is waiting for input (press i to type it)");
+                        }
+                        state.execute();
+                    }
+                    'b' if keydown => {
+                        state.execution_paused = true;
+                        if !state.step_back() {
+                            state.cmd_error("no earlier instruction in the history");
+                        }
+                    }
+                    'B' if keydown => {
+                        state.execution_paused = true;
+                        if !state.step_back_iteration() {
+                            state.cmd_error("no earlier loop iteration in the history");
+                        }
+                    }
                     ' ' if keydown => {
                         state.execution_paused = !state.execution_paused;
                     }
                     ':' if keydown => {
                         state.activity = Activity::Command;
                     }
+                    'i' if keydown => {
+                        state.activity = Activity::Input;
+                    }
                     _ => {}
                 },
                 KeyCode::Up if keydown => {
@@ -238,16 +414,21 @@ fn execute_command(command: &Command<Cell>, state: &mut InteractiveState) {
         Command::Pause => {
             state.execution_paused = true;
         }
+        // Changes made with commands aren't in the history, so it's cleared instead of
+        // letting it undo the script into a state it was never in.
         Command::SetInstructionPointer { idx } => {
             state.script.instruction_pointer = *idx;
+            state.history.clear();
         }
         Command::SetDataPointer { idx } => {
             state.runtime_context.data_pointer = *idx;
+            state.history.clear();
         }
         Command::SetData { idx, value } => {
             let idx = idx.unwrap_or(state.runtime_context.data_pointer);
             *state.runtime_context.get_cell(idx) = *value;
             state.runtime_context.fix_cell(idx);
+            state.history.clear();
         }
         Command::SetSpeed { speed } => {
             state.execution_clock_speed = *speed;
@@ -257,20 +438,145 @@ fn execute_command(command: &Command<Cell>, state: &mut InteractiveState) {
             ))
         }
         Command::SetBounds { lower, upper } => {
-            state.runtime_context.min_cell_value = lower.clone();
-            state.runtime_context.max_cell_value = upper.clone();
+            let context = &mut state.runtime_context;
+            context.min_cell_value = *lower;
+            context.max_cell_value = *upper;
+            // Cells outside of the new bounds wrap into them, like they would have if the
+            // script had been run with these bounds.
+            for i in 0..context.data.len() {
+                context.fix_cell(i);
+            }
+            state.history.clear();
+            state.cmd_info(format_args!("Set cell bounds to {}..{}", lower, upper));
+        }
+        Command::SetCellFormat { format } => {
+            state.cell_format = *format;
+            state.cmd_info(format_args!("Set cell format to {}", format));
+        }
+        Command::SetOverflowMode { mode } => {
+            state.runtime_context.overflow_mode = *mode;
+            state.cmd_info(format_args!("Set overflow mode to {}", mode));
         }
         Command::LoadScriptFromFile { path } => {
-            let content = match std::fs::read_to_string(&path) {
+            let content = match std::fs::read_to_string(path) {
                 Ok(content) => content,
                 Err(e) => {
                     state.cmd_error(e);
                     return;
                 }
             };
-            state.script = Script::new(content);
+            state.script = match Script::new(content) {
+                Ok(script) => script,
+                Err(e) => {
+                    state.cmd_error(e);
+                    return;
+                }
+            };
+            state.history.clear();
             state.cmd_info(format_args!("Loaded file {}", path.display()));
         }
+        Command::SaveSnapshot { path } => {
+            let input = {
+                let input = state.io.input.lock();
+                let start = (input.position() as usize).min(input.get_ref().len());
+                input.get_ref()[start..].to_vec()
+            };
+            let output = state.io.output.read().clone();
+            let snapshot = Snapshot::capture(&state.script, &state.runtime_context, input, output);
+            let result = std::fs::File::create(path)
+                .and_then(|file| snapshot.write_to(io::BufWriter::new(file)));
+            match result {
+                Ok(()) => state.cmd_info(format_args!("Saved snapshot to {}", path.display())),
+                Err(e) => state.cmd_error(e),
+            }
+        }
+        Command::RestoreSnapshot { path } => {
+            let snapshot = std::fs::File::open(path)
+                .map_err(Into::into)
+                .and_then(Snapshot::<Cell>::read_from);
+            let (snapshot, script) = match snapshot.and_then(|s| s.script().map(|v| (s, v))) {
+                Ok(loaded) => loaded,
+                Err(e) => {
+                    state.cmd_error(e);
+                    return;
+                }
+            };
+            state.script = script;
+            snapshot.restore(&mut state.runtime_context);
+            *state.io.input.lock() = Cursor::new(snapshot.input);
+            state.input_closed = false;
+            *state.io.output.write() = snapshot.output;
+            state.execution_paused = true;
+            state.last_executed_instruction = None;
+            state.history.clear();
+            state.cmd_info(format_args!("Restored snapshot from {}", path.display()));
+        }
+        Command::Rewind { cycle } => {
+            if *cycle > state.script.cycles {
+                state.cmd_error(format_args!("cycle {} hasn't been reached yet", cycle));
+                return;
+            }
+            state.execution_paused = true;
+            if state.rewind_to_cycle(*cycle) {
+                state.cmd_info(format_args!("Rewound to cycle {}", cycle));
+            } else {
+                let oldest = state.script.cycles;
+                state.cmd_error(format_args!(
+                    "the history only goes back to cycle {}",
+                    oldest
+                ));
+            }
+        }
+        Command::AddBreakpoint { breakpoint } => {
+            state.breakpoints.breakpoints.push(*breakpoint);
+            state.cmd_info(format_args!("Added breakpoint on {}", breakpoint));
+        }
+        Command::AddWatchpoint { watchpoint } => {
+            state.breakpoints.watchpoints.push(watchpoint.clone());
+            state.cmd_info(format_args!("Added watchpoint on {}", watchpoint));
+        }
+        Command::ClearBreakpoints => {
+            state.breakpoints.clear();
+            state.cmd_info("Cleared breakpoints and watchpoints");
+        }
+        Command::ImportInput { path } => {
+            match std::fs::read(path) {
+                Ok(bytes) => {
+                    state.queue_input(&bytes);
+                    state.cmd_info(format_args!(
+                        "Queued {} bytes of input from {}",
+                        bytes.len(),
+                        path.display()
+                    ));
+                }
+                Err(e) => state.cmd_error(e),
+            };
+        }
+        Command::ClearData => {
+            state.runtime_context.data.fill(0);
+            state.changed_cell.set(None);
+            state.history.clear();
+            state.cmd_info("Cleared the tape");
+        }
+        Command::ClearIo => {
+            state.clear_io();
+            state.history.clear();
+            state.cmd_info("Cleared the input and output");
+        }
+        Command::Restart => {
+            state.restart();
+            state.cmd_info("Restarted the script");
+        }
+        Command::Reset => {
+            state.runtime_context = new_runtime_context(&state.io, &state.changed_cell);
+            state.clear_io();
+            state.restart();
+            state.cmd_info("Reset the runtime");
+        }
+        Command::Execute { code } => match Script::new(code.clone()) {
+            Ok(snippet) => state.execute_snippet(snippet),
+            Err(e) => state.cmd_error(e),
+        },
         Command::Quit => {
             state.should_quit = true;
         }
@@ -298,11 +604,10 @@ fn handle_event_command(event: Event, state: &mut InteractiveState) {
                     }
                     CommandResult::CannotContinue { parts } => {
                         let mut errors = Vec::new();
-                        parts.iter().for_each(|part| match &part.state {
-                            CommandPartState::Invalid(Some(reason)) => {
+                        parts.iter().for_each(|part| {
+                            if let CommandPartState::Invalid(Some(reason)) = &part.state {
                                 errors.push(reason);
                             }
-                            _ => {}
                         });
 
                         state.cmd_error(format_args!(
@@ -393,12 +698,53 @@ fn handle_event_command(event: Event, state: &mut InteractiveState) {
                         );
                     }
                 }
-                return;
             }
         }
     }
 }
 
+/// Enter queues the typed line with a newline, like a terminal would. Ctrl+D queues it without
+/// one, or ends the input if nothing was typed.
+fn handle_event_input(event: Event, state: &mut InteractiveState) {
+    if let Event::Key(key) = event {
+        let is_down = key.kind != KeyEventKind::Release;
+        let end_of_line = match key.code {
+            KeyCode::Enter if is_down => Some(true),
+            KeyCode::Char('d') if is_down && key.modifiers.contains(KeyModifiers::CONTROL) => {
+                Some(false)
+            }
+            KeyCode::Esc if is_down => {
+                state.activity = Activity::Normal;
+                None
+            }
+            _ => {
+                state.input_line.handle_event(&event);
+                None
+            }
+        };
+        let Some(newline) = end_of_line else {
+            return;
+        };
+
+        let line = state.input_line.value();
+        if !newline && line.is_empty() {
+            state.input_closed = true;
+            state.cmd_info("Ended the input");
+            return;
+        }
+        match parse_escapes(line) {
+            Ok(mut bytes) => {
+                if newline {
+                    bytes.push(b'\n');
+                }
+                state.queue_input(&bytes);
+                state.input_line.reset();
+            }
+            Err(e) => state.cmd_error(e),
+        }
+    }
+}
+
 fn speed_diff(key_modifiers: KeyModifiers) -> Duration {
     let shift = key_modifiers.contains(KeyModifiers::SHIFT);
     let ctrl = key_modifiers.contains(KeyModifiers::CONTROL);
@@ -449,18 +795,68 @@ fn ui(frame: &mut Frame, state: &mut InteractiveState, io: &Rc<InteractiveIo>) {
     frame.render_widget(output_block, output_area);
 
     let data = RuntimeDataWidget::new()
+        .index_style(styles::CELL_INDEX)
+        .value_style(styles::VALUE)
+        .untouched_style(styles::CELL_UNTOUCHED)
+        .pointer_style(styles::CELL_POINTER)
+        .changed_style(styles::CELL_CHANGED)
         .title(" Data ")
         .padding(Padding::horizontal(1))
         .borders(Borders::ALL);
     frame.render_stateful_widget(data, data_area, state);
 
-    let misc_layout = Layout::horizontal([Min(10), Length(16), Length(16), Length(16), Length(32)]);
-    let [input_area, state_area, frame_counter_area, cycle_counter_area, speed_area] =
+    let misc_layout = Layout::horizontal([
+        Min(10),
+        Length(16),
+        Length(16),
+        Length(16),
+        Length(24),
+        Length(32),
+    ]);
+    let [input_area, state_area, frame_counter_area, cycle_counter_area, bounds_area, speed_area] =
         misc_layout.areas(misc_area);
 
+    let input_title = if state.input_closed {
+        " Input (ended) ".to_owned()
+    } else {
+        format!(" Input ({} queued) ", io.pending_input())
+    };
+    if state.activity == Activity::Input {
+        let input_block = Block::new()
+            .title(input_title)
+            .padding(Padding::horizontal(1))
+            .borders(Borders::ALL)
+            .border_style(styles::ACTIVE_BLOCK)
+            .title_style(styles::ACTIVE_BLOCK);
+        let input_line_area = input_block.inner(input_area);
+        frame.render_widget(input_block, input_area);
+
+        let visual_scroll = state
+            .input_line
+            .visual_scroll(input_line_area.width as usize);
+        frame.render_widget(
+            Paragraph::new(state.input_line.value()).scroll((0, visual_scroll as u16)),
+            input_line_area,
+        );
+        let visual_cursor = state.input_line.visual_cursor() - visual_scroll;
+        frame.set_cursor(input_line_area.x + visual_cursor as u16, input_line_area.y);
+    } else {
+        let input = io.input.lock();
+        let input_buffer = InputBufferWidget::new(input.get_ref(), input.position() as usize)
+            .consumed_style(styles::INPUT_CONSUMED)
+            .pending_style(styles::VALUE)
+            .escape_style(styles::INPUT_ESCAPE)
+            .title(input_title)
+            .padding(Padding::horizontal(1))
+            .borders(Borders::ALL);
+        frame.render_widget(input_buffer, input_area);
+    }
+
     let state_text = {
         if !state.script.has_remaining_instructions() {
             Span::styled("Finished", Style::new().fg(Color::LightRed).bold())
+        } else if state.awaiting_input() {
+            Span::styled("Awaiting input", Style::new().fg(Color::LightYellow))
         } else if state.execution_paused {
             Span::styled("Paused", Style::new().fg(Color::LightCyan))
         } else {
@@ -487,6 +883,21 @@ fn ui(frame: &mut Frame, state: &mut InteractiveState, io: &Rc<InteractiveIo>) {
         .borders(Borders::ALL);
     frame.render_widget(cycle_counter, cycle_counter_area);
 
+    let mut line = Line::default();
+    line.push_span(Span::styled(
+        state.runtime_context.min_cell_value.to_string(),
+        styles::VALUE,
+    ));
+    line.push_span(Span::styled("..", styles::VALUE_EXTRA));
+    line.push_span(Span::styled(
+        state.runtime_context.max_cell_value.to_string(),
+        styles::VALUE,
+    ));
+    let bounds_block = SimpleTextBlock::new(line)
+        .title("Cell bounds")
+        .borders(Borders::ALL);
+    frame.render_widget(bounds_block, bounds_area);
+
     let speed = humantime::format_duration(state.execution_clock_speed).to_string();
     let mut line = Line::default();
     line.push_span(Span::styled(speed, styles::VALUE));
@@ -549,12 +960,21 @@ mod styles {
 
     pub const VALUE: Style = Style::new().fg(Color::LightYellow);
     pub const VALUE_EXTRA: Style = Style::new();
-    pub const VALUE_MODIFIER: Style = Style::new().add_modifier(Modifier::ITALIC);
     pub const NEXT_INSTRUCTION: Style = Style::new().fg(Color::LightCyan);
     pub const CURRENT_INSTRUCTION: Style = Style::new()
         .fg(Color::LightBlue)
         .add_modifier(Modifier::BOLD);
     pub const INSTRUCTION: Style = Style::new();
+    pub const CELL_INDEX: Style = Style::new().add_modifier(Modifier::DIM);
+    pub const CELL_UNTOUCHED: Style = Style::new().add_modifier(Modifier::DIM);
+    pub const CELL_POINTER: Style = Style::new()
+        .fg(Color::LightCyan)
+        .add_modifier(Modifier::REVERSED);
+    pub const CELL_CHANGED: Style = Style::new()
+        .fg(Color::LightMagenta)
+        .add_modifier(Modifier::BOLD);
+    pub const INPUT_CONSUMED: Style = Style::new().add_modifier(Modifier::DIM);
+    pub const INPUT_ESCAPE: Style = Style::new().fg(Color::LightMagenta);
     pub const COMMENT: Style = Style::new()
         .add_modifier(Modifier::DIM)
         .add_modifier(Modifier::ITALIC);
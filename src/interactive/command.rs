@@ -1,15 +1,10 @@
-use crate::interactive::block_widget;
-use crate::CellType;
-use ratatui::layout::Alignment;
-use ratatui::text::Text;
-use ratatui::widgets::block::Title;
-use ratatui::widgets::{Block, Borders};
+use crate::interactive::runtime_data::CellFormat;
+use brainfuck::{Breakpoint, CellType, OverflowMode, Script, Watchpoint};
 use std::borrow::Cow;
 use std::fmt::{Display, Formatter};
 use std::fs::DirEntry;
 use std::io;
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
 use std::time::Duration;
 use uncased::UncasedStr;
 
@@ -22,7 +17,21 @@ pub enum Command<T: CellType> {
     SetData { idx: Option<usize>, value: T },
     SetSpeed { speed: Duration },
     SetBounds { lower: T, upper: T },
+    SetOverflowMode { mode: OverflowMode },
+    SetCellFormat { format: CellFormat },
     LoadScriptFromFile { path: PathBuf },
+    SaveSnapshot { path: PathBuf },
+    RestoreSnapshot { path: PathBuf },
+    ImportInput { path: PathBuf },
+    Rewind { cycle: usize },
+    AddBreakpoint { breakpoint: Breakpoint },
+    AddWatchpoint { watchpoint: Watchpoint<T> },
+    ClearBreakpoints,
+    ClearData,
+    ClearIo,
+    Restart,
+    Reset,
+    Execute { code: String },
     Quit,
 }
 #[derive(Clone)]
@@ -66,6 +75,7 @@ impl<'a> CommandPart<'a> {
         UncasedStr::new(self.content())
     }
 
+    #[allow(dead_code)]
     pub fn empty_at_end(&self) -> Self {
         Self {
             source: self.source,
@@ -92,7 +102,7 @@ impl<'a> CommandPart<'a> {
             state: CommandPartState::Ok,
         };
         if new_end >= self.end {
-            return (first, None)
+            return (first, None);
         }
         first.end = new_end;
         let second = Self {
@@ -104,9 +114,9 @@ impl<'a> CommandPart<'a> {
         if second.len() > 0 {
             return (first, Some(second));
         }
-        return (first, None)
+        (first, None)
     }
-    
+
     pub fn split_whitespace(&self) -> (Self, Option<Self>) {
         let mut first = Self {
             source: self.source,
@@ -162,6 +172,9 @@ pub enum TargetVariable {
     Data,
     Speed,
     Bound,
+    Cells,
+    Overflow,
+    Format,
 }
 impl TargetVariable {
     fn from_str(s: impl for<'a> PartialEq<&'a str>) -> Option<Self> {
@@ -180,11 +193,23 @@ impl TargetVariable {
         if s == "bound" {
             return Some(Self::Bound);
         }
+        if s == "cells" {
+            return Some(Self::Cells);
+        }
+        if s == "overflow" {
+            return Some(Self::Overflow);
+        }
+        if s == "format" {
+            return Some(Self::Format);
+        }
 
         None
     }
 }
-const AUTOCOMPLETE_COMMAND: &[&str] = &["start", "pause", "set", "load", "quit", "execute"];
+const AUTOCOMPLETE_COMMAND: &[&str] = &[
+    "start", "pause", "set", "load", "save", "restore", "import", "rewind", "break", "watch",
+    "clear", "restart", "reset", "quit", "execute",
+];
 const AUTOCOMPLETE_SET_VARIABLE: &[&str] = &[
     "instruction pointer",
     "ip",
@@ -194,11 +219,18 @@ const AUTOCOMPLETE_SET_VARIABLE: &[&str] = &[
     "d",
     "speed",
     "bound",
+    "cells",
+    "overflow",
+    "format",
 ];
+/// Widths in bits that `set cells` accepts, for unsigned cells of that width.
+const AUTOCOMPLETE_CELL_WIDTHS: &[&str] = &["8", "16", "32"];
+const BOUND_SEPARATOR: &str = "..";
+const AUTOCOMPLETE_CLEAR: &[&str] = &["data", "io", "breakpoints"];
 const EQUALS: &str = "=";
 const AUTOCOMPLETE_EQUAL: &[&str] = &[EQUALS];
-pub fn parse_command<T: CellType>(cmd_str: &str, autocomplete: bool) -> CommandResult<T> {
-    if cmd_str.len() < 1 {
+pub fn parse_command<T: CellType>(cmd_str: &str, autocomplete: bool) -> CommandResult<'_, T> {
+    if cmd_str.is_empty() {
         return CommandResult::TooShort {
             parts: Vec::new(),
             message: None,
@@ -232,7 +264,7 @@ pub fn parse_command<T: CellType>(cmd_str: &str, autocomplete: bool) -> CommandR
         let Some(remaining) = remaining else {
             return CommandResult::TooShort {
                 parts,
-                message: Some("variable name required".into()),
+                message: Some("variable name required"),
             };
         };
 
@@ -317,7 +349,7 @@ pub fn parse_command<T: CellType>(cmd_str: &str, autocomplete: bool) -> CommandR
                 };
             }
             variable => {
-                let (is_correct, mut remaining) =
+                let (is_correct, remaining) =
                     expect_equals_part(&mut parts, remaining, autocomplete);
                 if !is_correct {
                     return CommandResult::CannotContinue { parts };
@@ -373,20 +405,181 @@ pub fn parse_command<T: CellType>(cmd_str: &str, autocomplete: bool) -> CommandR
                             command: Command::SetSpeed { speed },
                         };
                     }
+                    TargetVariable::Overflow => {
+                        let (mut value_part, remaining) = remaining.split_whitespace();
+                        let mode = value_part.content().parse::<OverflowMode>();
+                        let Ok(mode) = mode else {
+                            value_part.state =
+                                CommandPartState::Invalid(mode.err().map(Into::into));
+                            if autocomplete {
+                                value_part.autocomplete_uncased(OverflowMode::NAMES);
+                            }
+                            parts.push(value_part);
+                            return CommandResult::CannotContinue { parts };
+                        };
+                        parts.push(value_part);
+                        if let Some(remaining) = remaining {
+                            parts.push(remaining);
+                        }
+                        return CommandResult::Parsed {
+                            parts,
+                            command: Command::SetOverflowMode { mode },
+                        };
+                    }
+                    TargetVariable::Format => {
+                        let (mut value_part, remaining) = remaining.split_whitespace();
+                        let format = value_part.content().parse::<CellFormat>();
+                        let Ok(format) = format else {
+                            value_part.state =
+                                CommandPartState::Invalid(format.err().map(Into::into));
+                            if autocomplete {
+                                value_part.autocomplete_uncased(CellFormat::NAMES);
+                            }
+                            parts.push(value_part);
+                            return CommandResult::CannotContinue { parts };
+                        };
+                        parts.push(value_part);
+                        if let Some(remaining) = remaining {
+                            parts.push(remaining);
+                        }
+                        return CommandResult::Parsed {
+                            parts,
+                            command: Command::SetCellFormat { format },
+                        };
+                    }
                     TargetVariable::Bound => {
-                        remaining.state = CommandPartState::Ok;
-                        todo!("Parsing of bounds");
-                        /*return CommandResult::Parsed {
+                        let (mut value_part, remaining) = remaining.split_whitespace();
+                        let Some(separator) = value_part.content().find(BOUND_SEPARATOR) else {
+                            value_part.state =
+                                CommandPartState::Invalid(Some("expected <lower>..<upper>".into()));
+                            parts.push(value_part);
+                            return CommandResult::CannotContinue { parts };
+                        };
+                        let (mut lower_part, upper_part) = value_part.split_at(separator);
+                        let upper_part = upper_part.expect("the separator follows the lower bound");
+                        let (mut separator_part, upper_part) =
+                            upper_part.split_at(BOUND_SEPARATOR.len());
+                        separator_part.state = CommandPartState::Ok;
+                        let lower = parse_number::<T>(&mut lower_part);
+                        let Some(mut upper_part) = upper_part else {
+                            parts.push(lower_part);
+                            parts.push(separator_part);
+                            return CommandResult::TooShort {
+                                parts,
+                                message: Some("expecting upper bound"),
+                            };
+                        };
+                        upper_part.state = CommandPartState::Ok;
+                        let upper = parse_number::<T>(&mut upper_part);
+
+                        // Cells start at zero and loops stop at zero, so zero has to be a
+                        // value cells can have.
+                        let mut bounds = None;
+                        if let (Ok(lower), Ok(upper)) = (lower, upper) {
+                            if lower > upper {
+                                upper_part.state = CommandPartState::Invalid(Some(
+                                    "upper bound is below the lower bound".into(),
+                                ));
+                            } else if lower > T::zero() {
+                                lower_part.state = CommandPartState::Invalid(Some(
+                                    "bounds have to include 0".into(),
+                                ));
+                            } else if upper < T::zero() {
+                                upper_part.state = CommandPartState::Invalid(Some(
+                                    "bounds have to include 0".into(),
+                                ));
+                            } else {
+                                bounds = Some((lower, upper));
+                            }
+                        }
+                        parts.push(lower_part);
+                        parts.push(separator_part);
+                        parts.push(upper_part);
+                        let Some((lower, upper)) = bounds else {
+                            return CommandResult::CannotContinue { parts };
+                        };
+                        if let Some(remaining) = remaining {
+                            parts.push(remaining);
+                        }
+                        return CommandResult::Parsed {
+                            parts,
+                            command: Command::SetBounds { lower, upper },
+                        };
+                    }
+                    TargetVariable::Cells => {
+                        let (mut value_part, remaining) = remaining.split_whitespace();
+                        value_part.state = CommandPartState::Ok;
+                        let upper = match value_part.content() {
+                            "8" => Some(u8::MAX as u64),
+                            "16" => Some(u16::MAX as u64),
+                            "32" => Some(u32::MAX as u64),
+                            _ => None,
+                        };
+                        let Some(upper) = upper else {
+                            value_part.state = CommandPartState::Invalid(Some(
+                                format!(
+                                    "unknown cell width '{}' (expected one of: {})",
+                                    value_part,
+                                    AUTOCOMPLETE_CELL_WIDTHS.join(", ")
+                                )
+                                .into(),
+                            ));
+                            if autocomplete {
+                                value_part.autocomplete_uncased(AUTOCOMPLETE_CELL_WIDTHS);
+                            }
+                            parts.push(value_part);
+                            return CommandResult::CannotContinue { parts };
+                        };
+                        let Ok(upper) = T::from_str_radix(&upper.to_string(), 10) else {
+                            value_part.state =
+                                CommandPartState::Invalid(Some("too wide for the cells".into()));
+                            parts.push(value_part);
+                            return CommandResult::CannotContinue { parts };
+                        };
+                        parts.push(value_part);
+                        if let Some(remaining) = remaining {
+                            parts.push(remaining);
+                        }
+                        return CommandResult::Parsed {
                             parts,
-                            command: Command::SetBounds { ..cargo run },
-                        };*/
+                            command: Command::SetBounds {
+                                lower: T::zero(),
+                                upper,
+                            },
+                        };
                     }
                 }
             }
         }
     }
 
-    if command_part.content_uncased() == "load" {
+    let file_command = ["load", "restore", "import"]
+        .into_iter()
+        .find(|name| command_part.content_uncased() == *name);
+    if let Some(name) = file_command {
+        command_part.state = CommandPartState::Ok;
+        parts.push(command_part);
+        let Some(mut file_part) = remaining else {
+            return CommandResult::TooShort {
+                parts,
+                message: Some("expected file name"),
+            };
+        };
+
+        let path = parse_existing_file(&mut file_part, autocomplete);
+        parts.push(file_part);
+
+        return CommandResult::Parsed {
+            parts,
+            command: match name {
+                "load" => Command::LoadScriptFromFile { path },
+                "restore" => Command::RestoreSnapshot { path },
+                _ => Command::ImportInput { path },
+            },
+        };
+    }
+
+    if command_part.content_uncased() == "save" {
         command_part.state = CommandPartState::Ok;
         parts.push(command_part);
         let Some(mut file_part) = remaining else {
@@ -397,103 +590,153 @@ pub fn parse_command<T: CellType>(cmd_str: &str, autocomplete: bool) -> CommandR
         };
 
         file_part.state = CommandPartState::Ok;
-        let file_path = std::env::current_dir()
-            .map(|v| v.join(file_part.content()))
-            .unwrap_or_else(|_| PathBuf::from(file_part.content()));
-
-        fn try_autocomplete(
-            path: &Path,
-            query_direct: bool,
-        ) -> io::Result<Option<Cow<'static, str>>> {
-            let Some(file_name) = path.file_name() else {
-                return if let Some(parent) = path.parent() {
-                    let mut read_dir = parent.read_dir()?;
-                    let Some(entry) = read_dir.next() else {
-                        return Ok(None);
-                    };
-                    let entry = entry?;
-                    let entry = entry.file_name().to_string_lossy().into_owned();
-                    Ok(Some(entry.into()))
-                } else {
-                    Ok(None)
-                };
+        let path = PathBuf::from(file_part.content());
+        parts.push(file_part);
+
+        return CommandResult::Parsed {
+            parts,
+            command: Command::SaveSnapshot { path },
+        };
+    }
+
+    if command_part.content_uncased() == "rewind" {
+        command_part.state = CommandPartState::Ok;
+        parts.push(command_part);
+        let Some(remaining) = remaining else {
+            return CommandResult::TooShort {
+                parts,
+                message: Some("expected cycle"),
             };
-            let file_name = file_name.to_string_lossy();
+        };
 
-            let (target, start_with) = if query_direct {
-                (Some(path), "")
-            } else {
-                (path.parent(), file_name.as_ref())
+        let (mut cycle_part, remaining) = remaining.split_whitespace();
+        let Ok(cycle) = parse_number::<usize>(&mut cycle_part) else {
+            parts.push(cycle_part);
+            return CommandResult::CannotContinue { parts };
+        };
+        parts.push(cycle_part);
+        if let Some(remaining) = remaining {
+            parts.push(remaining);
+        }
+        return CommandResult::Parsed {
+            parts,
+            command: Command::Rewind { cycle },
+        };
+    }
+
+    let is_break = command_part.content_uncased() == "break";
+    if is_break || command_part.content_uncased() == "watch" {
+        command_part.state = CommandPartState::Ok;
+        parts.push(command_part);
+        let Some(remaining) = remaining else {
+            return CommandResult::TooShort {
+                parts,
+                message: Some(if is_break {
+                    "expected #, @position or instruction index"
+                } else {
+                    "expected cell:read, cell:write or cell=value"
+                }),
             };
-            if let Some(parent) = target {
-                let dir = parent.read_dir()?;
-
-                let mut found_suggestion: Option<(DirEntry, String)> = None;
-                for entry in dir {
-                    let entry = entry?;
-                    let entry_name = entry.file_name().to_string_lossy().into_owned();
-                    if entry_name.starts_with(start_with) {
-                        if let Some((previous_entry, previous_name)) = &mut found_suggestion {
-                            if entry_name.as_str() < previous_name.as_str() {
-                                *previous_entry = entry;
-                                *previous_name = entry_name;
-                            }
-                        } else {
-                            found_suggestion = Some((entry, entry_name))
-                        }
-                    }
-                }
-                if let Some((suggestion, mut suggestion_str)) = found_suggestion {
-                    if file_name == suggestion_str && suggestion.path().is_dir() {
-                        suggestion_str.push(std::path::MAIN_SEPARATOR);
-                    }
+        };
 
-                    return Ok(Some(suggestion_str.into()));
-                }
+        let (mut point_part, remaining) = remaining.split_whitespace();
+        point_part.state = CommandPartState::Ok;
+        let command = if is_break {
+            point_part
+                .content()
+                .parse()
+                .map(|breakpoint| Command::AddBreakpoint { breakpoint })
+        } else {
+            point_part
+                .content()
+                .parse()
+                .map(|watchpoint| Command::AddWatchpoint { watchpoint })
+        };
+        let command = match command {
+            Ok(command) => command,
+            Err(e) => {
+                point_part.state = CommandPartState::Invalid(Some(e.into()));
+                parts.push(point_part);
+                return CommandResult::CannotContinue { parts };
             }
-
-            Ok(None)
+        };
+        parts.push(point_part);
+        if let Some(remaining) = remaining {
+            parts.push(remaining);
         }
+        return CommandResult::Parsed { parts, command };
+    }
 
-        if !file_path.exists() {
-            file_part.state = CommandPartState::Invalid(Some("file not found".into()))
-        } else if !file_path.is_file() {
-            file_part.state =
-                CommandPartState::Invalid(Some("path does not refer to a file".into()))
-        }
-        if autocomplete {
-            // TODO: Fix the try_autocomplete function so that the parent path is
-            //       the input instead of it guessing the parent.
-            //       The result of not doing that is this unrefined salad.
-            let at_end = file_part.content().ends_with(std::path::is_separator);
-
-            if let Ok(Some(suggestion)) = try_autocomplete(&file_path, at_end) {
-                let base = PathBuf::from(file_part.content());
-                let full_suggestion = base.parent();
-                file_part.state = CommandPartState::Autocomplete {
-                    suggestion: if at_end {
-                        base.join(suggestion.as_ref())
-                            .to_string_lossy()
-                            .into_owned()
-                            .into()
-                    } else if let Some(parent) = full_suggestion {
-                        parent
-                            .join(suggestion.as_ref())
-                            .to_string_lossy()
-                            .into_owned()
-                            .into()
-                    } else {
-                        suggestion
-                    },
-                };
+    if command_part.content_uncased() == "clear" {
+        command_part.state = CommandPartState::Ok;
+        parts.push(command_part);
+        let Some(mut target_part) = remaining else {
+            return CommandResult::TooShort {
+                parts,
+                message: Some("expected what to clear"),
+            };
+        };
+        let target = target_part.content_uncased();
+        let command = if target == "data" {
+            Command::ClearData
+        } else if target == "io" {
+            Command::ClearIo
+        } else if target == "breakpoints" {
+            Command::ClearBreakpoints
+        } else {
+            target_part.state =
+                CommandPartState::Invalid(Some(format!("cannot clear '{}'", target_part).into()));
+            if autocomplete {
+                target_part.autocomplete_uncased(AUTOCOMPLETE_CLEAR);
             }
-        }
-        let path = PathBuf::from(file_part.content());
-        parts.push(file_part);
+            parts.push(target_part);
+            return CommandResult::CannotContinue { parts };
+        };
+        target_part.state = CommandPartState::Ok;
+        parts.push(target_part);
+        return CommandResult::Parsed { parts, command };
+    }
+
+    if command_part.content_uncased() == "restart" {
+        command_part.state = CommandPartState::Ok;
+        parts.push(command_part);
+        return CommandResult::Parsed {
+            parts,
+            command: Command::Restart,
+        };
+    }
+
+    if command_part.content_uncased() == "reset" {
+        command_part.state = CommandPartState::Ok;
+        parts.push(command_part);
+        return CommandResult::Parsed {
+            parts,
+            command: Command::Reset,
+        };
+    }
+
+    if command_part.content_uncased() == "execute" {
+        command_part.state = CommandPartState::Ok;
+        parts.push(command_part);
+        let Some(mut code_part) = remaining else {
+            return CommandResult::TooShort {
+                parts,
+                message: Some("expected code"),
+            };
+        };
 
+        // Everything after the command is code, spaces included.
+        code_part.state = CommandPartState::Ok;
+        if let Err(e) = Script::new(code_part.content().to_owned()) {
+            code_part.state = CommandPartState::Invalid(Some(e.to_string().into()));
+            parts.push(code_part);
+            return CommandResult::CannotContinue { parts };
+        }
+        let code = code_part.content().to_owned();
+        parts.push(code_part);
         return CommandResult::Parsed {
             parts,
-            command: Command::LoadScriptFromFile { path },
+            command: Command::Execute { code },
         };
     }
 
@@ -516,20 +759,108 @@ pub fn parse_command<T: CellType>(cmd_str: &str, autocomplete: bool) -> CommandR
     CommandResult::CannotContinue { parts }
 }
 
+/// Marks whether `file_part` names an existing file and suggests completions for it.
+fn parse_existing_file(file_part: &mut CommandPart, autocomplete: bool) -> PathBuf {
+    file_part.state = CommandPartState::Ok;
+    let file_path = std::env::current_dir()
+        .map(|v| v.join(file_part.content()))
+        .unwrap_or_else(|_| PathBuf::from(file_part.content()));
+
+    fn try_autocomplete(path: &Path, query_direct: bool) -> io::Result<Option<Cow<'static, str>>> {
+        let Some(file_name) = path.file_name() else {
+            return if let Some(parent) = path.parent() {
+                let mut read_dir = parent.read_dir()?;
+                let Some(entry) = read_dir.next() else {
+                    return Ok(None);
+                };
+                let entry = entry?;
+                let entry = entry.file_name().to_string_lossy().into_owned();
+                Ok(Some(entry.into()))
+            } else {
+                Ok(None)
+            };
+        };
+        let file_name = file_name.to_string_lossy();
+
+        let (target, start_with) = if query_direct {
+            (Some(path), "")
+        } else {
+            (path.parent(), file_name.as_ref())
+        };
+        if let Some(parent) = target {
+            let dir = parent.read_dir()?;
+
+            let mut found_suggestion: Option<(DirEntry, String)> = None;
+            for entry in dir {
+                let entry = entry?;
+                let entry_name = entry.file_name().to_string_lossy().into_owned();
+                if entry_name.starts_with(start_with) {
+                    if let Some((previous_entry, previous_name)) = &mut found_suggestion {
+                        if entry_name.as_str() < previous_name.as_str() {
+                            *previous_entry = entry;
+                            *previous_name = entry_name;
+                        }
+                    } else {
+                        found_suggestion = Some((entry, entry_name))
+                    }
+                }
+            }
+            if let Some((suggestion, mut suggestion_str)) = found_suggestion {
+                if file_name == suggestion_str && suggestion.path().is_dir() {
+                    suggestion_str.push(std::path::MAIN_SEPARATOR);
+                }
+
+                return Ok(Some(suggestion_str.into()));
+            }
+        }
+
+        Ok(None)
+    }
+
+    if !file_path.exists() {
+        file_part.state = CommandPartState::Invalid(Some("file not found".into()))
+    } else if !file_path.is_file() {
+        file_part.state = CommandPartState::Invalid(Some("path does not refer to a file".into()))
+    }
+    if autocomplete {
+        // TODO: Fix the try_autocomplete function so that the parent path is
+        //       the input instead of it guessing the parent.
+        //       The result of not doing that is this unrefined salad.
+        let at_end = file_part.content().ends_with(std::path::is_separator);
+
+        if let Ok(Some(suggestion)) = try_autocomplete(&file_path, at_end) {
+            let base = PathBuf::from(file_part.content());
+            let full_suggestion = base.parent();
+            file_part.state = CommandPartState::Autocomplete {
+                suggestion: if at_end {
+                    base.join(suggestion.as_ref())
+                        .to_string_lossy()
+                        .into_owned()
+                        .into()
+                } else if let Some(parent) = full_suggestion {
+                    parent
+                        .join(suggestion.as_ref())
+                        .to_string_lossy()
+                        .into_owned()
+                        .into()
+                } else {
+                    suggestion
+                },
+            };
+        }
+    }
+    PathBuf::from(file_part.content())
+}
+
 fn expect_equals_part<'a>(
     parts: &mut Vec<CommandPart<'a>>,
-    mut remaining: CommandPart<'a>,
+    remaining: CommandPart<'a>,
     autocomplete: bool,
 ) -> (bool, Option<CommandPart<'a>>) {
-    let (mut equals_part, remaining) = remaining.split_whitespace() else {
-        if autocomplete {
-            remaining.autocomplete_uncased(AUTOCOMPLETE_EQUAL);
-        }
-
-        return (false, Some(remaining));
-    };
+    let (mut equals_part, remaining) = remaining.split_whitespace();
     equals_part.state = CommandPartState::Ok;
-    if equals_part.content() != EQUALS {
+    let is_correct = equals_part.content() == EQUALS;
+    if !is_correct {
         equals_part.state =
             CommandPartState::Invalid(Some(format!("expected '=', got '{}'", equals_part).into()));
         if autocomplete {
@@ -537,32 +868,35 @@ fn expect_equals_part<'a>(
         }
     }
     parts.push(equals_part);
-    (true, remaining)
+    (is_correct, remaining)
 }
 
 // Really this should only require something like T: FromStrRadix but I can't be bothered
 fn parse_number<T: CellType>(current: &mut CommandPart) -> Result<T, ()> {
-    let mut str = current.content();
-    let mut ustr = UncasedStr::new(str);
+    let content = current.content();
+    let (sign, mut str) = match content.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", content),
+    };
     let mut radix = 10;
 
-    let first_two = &ustr[0..2.min(ustr.len())];
-    let last_one = &ustr[ustr.len().checked_sub(1).unwrap_or(0)..];
-    if first_two == "0b" {
+    // Byte slices of the input could split a character, so only look at it with `get`.
+    let prefix = str.get(..2).map(UncasedStr::new);
+    if prefix == Some("0b".into()) {
         radix = 2;
         str = &str[2..];
-    } else if first_two == "0o" {
+    } else if prefix == Some("0o".into()) {
         radix = 8;
         str = &str[2..];
-    } else if first_two == "0x" {
+    } else if prefix == Some("0x".into()) {
         radix = 16;
         str = &str[2..];
-    } else if last_one == "h" {
+    } else if let Some(rest) = str.strip_suffix(['h', 'H']) {
         radix = 16;
-        str = &str[..str.len() - 1];
+        str = rest;
     }
 
-    let result = T::from_str_radix(str, radix);
+    let result = T::from_str_radix(&format!("{}{}", sign, str), radix);
     if result.is_err() {
         current.state = CommandPartState::Invalid(Some("not a valid number".into()));
     }
@@ -577,3 +911,97 @@ fn parse_duration(current: &mut CommandPart) -> Result<Duration, ()> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(cmd: &str) -> Option<Command<i64>> {
+        match parse_command::<i64>(cmd, false) {
+            CommandResult::Parsed { command, .. } => Some(command),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn state_commands_parse() {
+        assert!(matches!(parse("clear data"), Some(Command::ClearData)));
+        assert!(matches!(parse("clear io"), Some(Command::ClearIo)));
+        assert!(matches!(parse("restart"), Some(Command::Restart)));
+        assert!(matches!(parse("reset"), Some(Command::Reset)));
+    }
+
+    #[test]
+    fn set_needs_an_equals_sign() {
+        assert!(matches!(
+            parse("set dp = 3"),
+            Some(Command::SetDataPointer { idx: 3 })
+        ));
+        assert!(matches!(
+            parse_command::<i64>("set dp 3", false),
+            CommandResult::CannotContinue { .. }
+        ));
+    }
+
+    #[test]
+    fn bounds_parse() {
+        assert!(matches!(
+            parse("set bound = -128..127"),
+            Some(Command::SetBounds {
+                lower: -128,
+                upper: 127
+            })
+        ));
+        assert!(matches!(
+            parse("set bound = 0..0xFF"),
+            Some(Command::SetBounds {
+                lower: 0,
+                upper: 255
+            })
+        ));
+        assert!(parse("set bound = 10..5").is_none());
+        assert!(parse("set bound = 1..5").is_none());
+        assert!(parse("set bound = -5..-1").is_none());
+        assert!(parse("set bound = 5").is_none());
+    }
+
+    #[test]
+    fn cell_widths_parse() {
+        assert!(matches!(
+            parse("set cells = 16"),
+            Some(Command::SetBounds {
+                lower: 0,
+                upper: 65535
+            })
+        ));
+        assert!(matches!(
+            parse_command::<u8>("set cells = 16", false),
+            CommandResult::CannotContinue { .. }
+        ));
+        assert!(parse("set cells = 12").is_none());
+    }
+
+    #[test]
+    fn numbers_parse() {
+        let number = |source: &str| {
+            let mut part = CommandPart {
+                source,
+                start: 0,
+                end: source.len(),
+                state: CommandPartState::Ok,
+            };
+            parse_number::<i64>(&mut part).ok()
+        };
+        assert_eq!(number("42"), Some(42));
+        assert_eq!(number("-42"), Some(-42));
+        assert_eq!(number("0b101"), Some(5));
+        assert_eq!(number("0o17"), Some(15));
+        assert_eq!(number("0X1f"), Some(31));
+        assert_eq!(number("-1Fh"), Some(-31));
+        assert_eq!(number("4x"), None);
+        // Characters wider than a byte mustn't be split.
+        assert_eq!(number("é"), None);
+        assert_eq!(number("0é"), None);
+        assert_eq!(number("1é"), None);
+    }
+}
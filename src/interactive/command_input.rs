@@ -1,6 +1,6 @@
 use crate::interactive::command::{parse_command, CommandPartState, CommandResult};
 use crate::interactive::widget_setter;
-use crate::CellType;
+use brainfuck::CellType;
 use ratatui::prelude::*;
 use ratatui::style::Style;
 use ratatui::text::{Line, Span};
@@ -9,7 +9,6 @@ use std::borrow::Cow;
 use std::collections::VecDeque;
 use std::marker::PhantomData;
 use std::mem;
-use std::pin::Pin;
 use tui_input::Input;
 
 pub struct CommandInput<T: CellType> {
@@ -19,7 +18,6 @@ pub struct CommandInput<T: CellType> {
     error_style: Style,
     error_comment_style: Style,
     suggestion_style: Style,
-    cursor_style: Style,
     scroll: (u16, u16),
 }
 impl<T> CommandInput<T>
@@ -28,13 +26,12 @@ where
 {
     pub fn new() -> Self {
         Self {
-            _data: PhantomData::default(),
+            _data: PhantomData,
             base_style: Style::new(),
             ignored_style: Style::new(),
             error_style: Style::new(),
             error_comment_style: Style::new(),
             suggestion_style: Style::new(),
-            cursor_style: Style::new(),
             scroll: (0, 0),
         }
     }
@@ -45,7 +42,6 @@ widget_setter! { impl<T: CellType> CommandInput<T> {
     error_style: Style,
     error_comment_style: Style,
     suggestion_style: Style,
-    cursor_style: Style,
     scroll: (u16, u16)
 } }
 pub struct CommandInputState<T: CellType> {
@@ -109,7 +105,7 @@ impl<T: CellType> Drop for OwnedCommandResult<T> {
 impl<T: CellType> OwnedCommandResult<T> {
     pub fn empty() -> Self {
         Self {
-            source: unsafe { Box::into_raw(Box::new(Cow::Borrowed(""))) },
+            source: Box::into_raw(Box::new(Cow::Borrowed(""))),
             result: CommandResult::TooShort {
                 parts: Vec::new(),
                 message: None,
@@ -118,7 +114,7 @@ impl<T: CellType> OwnedCommandResult<T> {
     }
     pub fn parse(data: String, autocomplete: bool) -> Self {
         let mut owned = Self {
-            source: unsafe { Box::into_raw(Box::new(data.into())) },
+            source: Box::into_raw(Box::new(data.into())),
             result: CommandResult::TooShort {
                 parts: Vec::new(),
                 message: None,
@@ -204,16 +200,16 @@ where
             ));
             main_line_length = main_line.width();
         }
-        match &state.current.result {
-            CommandResult::TooShort {
-                message: Some(message),
-                ..
-            } => messages.push(Message {
+        if let CommandResult::TooShort {
+            message: Some(message),
+            ..
+        } = &state.current.result
+        {
+            messages.push(Message {
                 start: main_line_length,
                 style: self.error_comment_style,
                 content: { *message }.into(),
-            }),
-            _ => {}
+            })
         }
 
         let mut temp_empty = String::with_capacity(main_line_length + 2);
@@ -0,0 +1,214 @@
+use crate::interactive::{Cell, InteractiveState};
+use brainfuck::Instruction;
+use std::collections::VecDeque;
+
+/// Number of executed instructions that can be undone.
+/// Older changes are forgotten, so long runs only keep the most recent ones.
+pub const HISTORY_LENGTH: usize = 100_000;
+
+/// What executing one instruction changed, so that it can be undone.
+pub struct Change {
+    instruction_pointer: usize,
+    cycles: usize,
+    data_pointer: usize,
+    origin: usize,
+    tape_len: usize,
+    /// Index and previous value of the cell the instruction wrote to, if it wrote to one.
+    cell: Option<(usize, Cell)>,
+    input_position: u64,
+    output_len: usize,
+}
+impl Change {
+    /// Records the state the next instruction of `state` is about to change.
+    pub fn before_step(state: &InteractiveState) -> Self {
+        let context = &state.runtime_context;
+        let writes_cell = matches!(
+            state.script.instruction(),
+            Some(Instruction::IncrementData | Instruction::DecrementData | Instruction::AcceptData)
+        );
+        Self {
+            instruction_pointer: state.script.instruction_pointer,
+            cycles: state.script.cycles,
+            data_pointer: context.data_pointer,
+            origin: context.origin,
+            tape_len: context.data.len(),
+            cell: writes_cell.then(|| {
+                let index = context.data_pointer;
+                (index, context.read_cell(index))
+            }),
+            input_position: state.io.input.lock().position(),
+            output_len: state.io.output.read().len(),
+        }
+    }
+
+    /// Puts `state` back to how it was before the instruction ran.
+    fn undo(self, state: &mut InteractiveState) {
+        let context = &mut state.runtime_context;
+        // Cells added before the start of a bidirectional tape shifted the others to the
+        // right, so they have to go before the old indices are valid again.
+        context.data.drain(..context.origin - self.origin);
+        context.data.truncate(self.tape_len);
+        context.origin = self.origin;
+        context.data_pointer = self.data_pointer;
+        if let Some((index, value)) = self.cell {
+            *context.get_cell(index) = value;
+        }
+        context.output_len = self.output_len;
+        state.io.input.lock().set_position(self.input_position);
+        state.io.output.write().truncate(self.output_len);
+        state.script.instruction_pointer = self.instruction_pointer;
+        state.script.cycles = self.cycles;
+    }
+}
+
+/// The most recent changes made by executing instructions, newest last.
+#[derive(Default)]
+pub struct History {
+    changes: VecDeque<Change>,
+}
+impl History {
+    pub fn record(&mut self, change: Change) {
+        if self.changes.len() == HISTORY_LENGTH {
+            self.changes.pop_front();
+        }
+        self.changes.push_back(change);
+    }
+    pub fn clear(&mut self) {
+        self.changes.clear();
+    }
+}
+
+impl InteractiveState {
+    /// Undoes the last executed instruction. Returns `false` if there is nothing to undo.
+    pub fn step_back(&mut self) -> bool {
+        let Some(change) = self.history.changes.pop_back() else {
+            return false;
+        };
+        change.undo(self);
+        self.changed_cell.set(None);
+        self.last_executed_instruction = self
+            .history
+            .changes
+            .back()
+            .and_then(|change| self.script.instructions.get(change.instruction_pointer))
+            .copied();
+        true
+    }
+
+    /// Goes back to the start of the current iteration of the innermost loop, or to the start of
+    /// the previous iteration if it's already there.
+    /// Returns `false` if it isn't in a loop or the history doesn't go back far enough, in which
+    /// case it goes back as far as it can.
+    pub fn step_back_iteration(&mut self) -> bool {
+        let pointer = self.script.instruction_pointer;
+        // The innermost loop is the closest `[` before the instruction pointer whose `]` isn't
+        // before it as well.
+        let start = self.script.instructions[..pointer.min(self.script.instructions.len())]
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, instruction)| {
+                instruction.instruction == Instruction::JumpForwardsIfZero
+                    && instruction
+                        .partner
+                        .is_some_and(|partner| partner >= pointer)
+            })
+            .map(|(index, _)| index + 1);
+        let Some(start) = start else {
+            return false;
+        };
+        while self.step_back() {
+            if self.script.instruction_pointer == start {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Goes back to when `cycle` cycles had run. Returns `false` if the history doesn't go back
+    /// that far, in which case it goes back as far as it can.
+    pub fn rewind_to_cycle(&mut self, cycle: usize) -> bool {
+        while self.script.cycles > cycle {
+            if !self.step_back() {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interactive::InteractiveState;
+    use brainfuck::Script;
+    use std::io::Cursor;
+
+    fn state(source: &str, input: &[u8]) -> InteractiveState {
+        let state = InteractiveState::new(Script::new(source.to_owned()).expect("script loads"));
+        *state.io.input.lock() = Cursor::new(input.to_vec());
+        state
+    }
+
+    fn run(state: &mut InteractiveState, cycles: usize) {
+        for _ in 0..cycles {
+            state.execute();
+        }
+    }
+
+    #[test]
+    fn step_back_undoes_every_instruction() {
+        let mut state = state("+>,.<-", b"A");
+        run(&mut state, 6);
+        assert_eq!(state.runtime_context.data, [0, 65]);
+        assert_eq!(*state.io.output.read(), b"A");
+
+        assert!(state.step_back());
+        assert_eq!(state.runtime_context.data, [1, 65]);
+        assert_eq!(state.script.instruction_pointer, 5);
+        for _ in 0..5 {
+            assert!(state.step_back());
+        }
+        assert!(!state.step_back());
+        assert!(state.runtime_context.data.iter().all(|&cell| cell == 0));
+        assert_eq!(state.runtime_context.data_pointer, 0);
+        assert_eq!(state.script.instruction_pointer, 0);
+        assert_eq!(state.script.cycles, 0);
+        assert_eq!(state.io.input.lock().position(), 0);
+        assert!(state.io.output.read().is_empty());
+
+        // Running it again reads the same input.
+        run(&mut state, 6);
+        assert_eq!(*state.io.output.read(), b"A");
+    }
+
+    #[test]
+    fn rewind_goes_back_to_a_cycle() {
+        let mut state = state("+++[>++<-]", b"");
+        run(&mut state, 20);
+        let cycles = state.script.cycles;
+        assert!(state.rewind_to_cycle(3));
+        assert_eq!(state.script.cycles, 3);
+        assert_eq!(state.runtime_context.data, [3]);
+
+        run(&mut state, cycles - 3);
+        assert_eq!(state.script.cycles, cycles);
+        state.history.clear();
+        assert!(!state.rewind_to_cycle(0));
+        assert_eq!(state.script.cycles, cycles);
+    }
+
+    #[test]
+    fn step_back_iteration_goes_to_the_loop_start() {
+        let mut state = state("++[>+<-]", b"");
+        // Into the second iteration, just after `>+`.
+        run(&mut state, 10);
+        assert_eq!(state.script.instruction_pointer, 5);
+        assert!(state.step_back_iteration());
+        assert_eq!(state.script.instruction_pointer, 3);
+        assert_eq!(state.runtime_context.data, [1, 1]);
+        // Already at the start, so it goes to the previous iteration.
+        assert!(state.step_back_iteration());
+        assert_eq!(state.script.instruction_pointer, 3);
+        assert_eq!(state.runtime_context.data[0], 2);
+    }
+}
@@ -0,0 +1,148 @@
+use crate::interactive::{block_widget, widget_setter};
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Paragraph};
+use std::borrow::Cow;
+
+/// Turns typed input into bytes.
+/// Supports `\n`, `\r`, `\t`, `\0`, `\\` and `\xHH` for any byte; everything else is taken
+/// as UTF-8.
+pub fn parse_escapes(s: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            let mut buf = [0u8; 4];
+            bytes.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        let byte = match chars.next() {
+            Some('n') => b'\n',
+            Some('r') => b'\r',
+            Some('t') => b'\t',
+            Some('0') => b'\0',
+            Some('\\') => b'\\',
+            Some('x') => {
+                let digits: String = chars.by_ref().take(2).collect();
+                if digits.len() != 2 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(format!(
+                        "expected two hex digits after '\\x', got '{}'",
+                        digits
+                    ));
+                }
+                u8::from_str_radix(&digits, 16).expect("two hex digits always make a byte")
+            }
+            Some(other) => return Err(format!("unknown escape sequence '\\{}'", other)),
+            None => return Err("unfinished escape sequence at the end".to_owned()),
+        };
+        bytes.push(byte);
+    }
+    Ok(bytes)
+}
+
+/// Shows a byte the way it would be typed with [`parse_escapes`].
+pub fn escape_byte(byte: u8) -> Cow<'static, str> {
+    match byte {
+        b'\n' => "\\n".into(),
+        b'\r' => "\\r".into(),
+        b'\t' => "\\t".into(),
+        b'\0' => "\\0".into(),
+        b'\\' => "\\\\".into(),
+        b' '..=b'~' => (byte as char).to_string().into(),
+        _ => format!("\\x{:02X}", byte).into(),
+    }
+}
+
+/// Shows the input queued for the script on one line.
+/// Bytes the script has already read are kept in view before the ones it hasn't, so that it's
+/// clear where the next `,` will read from.
+pub struct InputBufferWidget<'a> {
+    block: Block<'a>,
+    input: &'a [u8],
+    position: usize,
+    consumed_style: Style,
+    pending_style: Style,
+    escape_style: Style,
+}
+impl<'a> InputBufferWidget<'a> {
+    pub fn new(input: &'a [u8], position: usize) -> Self {
+        Self {
+            block: Block::new(),
+            input,
+            position: position.min(input.len()),
+            consumed_style: Style::new(),
+            pending_style: Style::new(),
+            escape_style: Style::new(),
+        }
+    }
+}
+block_widget!(InputBufferWidget => block);
+widget_setter! { impl<'a> InputBufferWidget<'a> {
+    consumed_style: Style,
+    pending_style: Style,
+    escape_style: Style
+} }
+impl<'a> Widget for InputBufferWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let content_area = self.block.inner(area);
+        self.block.render(area, buf);
+
+        // Up to a third of the width goes to input that has already been read.
+        let mut start = self.position;
+        let mut consumed_width = 0;
+        while start > 0 {
+            let width = escape_byte(self.input[start - 1]).len();
+            if (consumed_width + width) * 3 > content_area.width as usize {
+                break;
+            }
+            consumed_width += width;
+            start -= 1;
+        }
+
+        let spans = self.input[start..]
+            .iter()
+            .enumerate()
+            .map(|(i, &byte)| {
+                let escaped = escape_byte(byte);
+                let mut style = if start + i < self.position {
+                    self.consumed_style
+                } else {
+                    self.pending_style
+                };
+                if escaped.len() > 1 {
+                    style = style.patch(self.escape_style);
+                }
+                Span::styled(escaped, style)
+            })
+            // Nothing past the right edge is shown anyway.
+            .take(content_area.width as usize);
+        Paragraph::new(Line::from_iter(spans)).render(content_area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_parse() {
+        assert_eq!(parse_escapes("a\\nb"), Ok(b"a\nb".to_vec()));
+        assert_eq!(parse_escapes("\\x41\\x7f"), Ok(vec![b'A', 0x7F]));
+        assert_eq!(parse_escapes("\\r\\t\\0\\\\"), Ok(b"\r\t\0\\".to_vec()));
+        assert_eq!(parse_escapes("é"), Ok("é".as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn bad_escapes_are_rejected() {
+        assert!(parse_escapes("\\x4").is_err());
+        assert!(parse_escapes("\\xg1").is_err());
+        assert!(parse_escapes("\\q").is_err());
+        assert!(parse_escapes("abc\\").is_err());
+    }
+
+    #[test]
+    fn escaped_bytes_parse_back() {
+        for byte in 0..=u8::MAX {
+            assert_eq!(parse_escapes(&escape_byte(byte)), Ok(vec![byte]));
+        }
+    }
+}
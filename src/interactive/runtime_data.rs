@@ -1,26 +1,152 @@
-use crate::interactive::{block_widget, Cell};
+use crate::interactive::{block_widget, widget_setter, Cell};
+use brainfuck::{setting_enum, TapePolicy, DEFAULT_CIRCULAR_TAPE_LENGTH};
 use ratatui::prelude::*;
-use ratatui::widgets::block::Title;
-use ratatui::widgets::{Block, Borders, Table};
+use ratatui::widgets::Block;
 
+setting_enum! {
+    /// How the values of cells are shown.
+    #[derive(Default)]
+    pub enum CellFormat("cell format") {
+        #[default]
+        Decimal => "decimal",
+        Hex => "hex",
+        /// Printable characters as themselves, a few control characters as escapes and anything
+        /// else as a dot.
+        Ascii => "ascii",
+    }
+}
+impl CellFormat {
+    pub fn format(self, value: Cell) -> String {
+        match self {
+            CellFormat::Decimal => value.to_string(),
+            CellFormat::Hex if value < 0 => format!("-{:X}", value.unsigned_abs()),
+            CellFormat::Hex => format!("{:X}", value),
+            CellFormat::Ascii => match u8::try_from(value) {
+                Ok(b'\0') => "\\0".to_owned(),
+                Ok(b'\t') => "\\t".to_owned(),
+                Ok(b'\n') => "\\n".to_owned(),
+                Ok(b'\r') => "\\r".to_owned(),
+                Ok(byte) if byte.is_ascii_graphic() || byte == b' ' => (byte as char).to_string(),
+                _ => "·".to_owned(),
+            },
+        }
+    }
+}
+/// Shows the tape as rows of cells, with the index of every cell above its value.
+/// Indices count from the origin, so cells to the left of it are negative.
+/// The row with the data pointer is kept in the middle, so the view only scrolls when the
+/// pointer moves to another row.
 pub struct RuntimeDataWidget<'a> {
     block: Block<'a>,
+    index_style: Style,
+    value_style: Style,
+    untouched_style: Style,
+    pointer_style: Style,
+    changed_style: Style,
 }
 impl<'a> RuntimeDataWidget<'a> {
     pub fn new() -> Self {
         Self {
             block: Block::new(),
+            index_style: Style::new(),
+            value_style: Style::new(),
+            untouched_style: Style::new(),
+            pointer_style: Style::new(),
+            changed_style: Style::new(),
         }
     }
 }
 block_widget!(RuntimeDataWidget => block);
+widget_setter! { impl<'a> RuntimeDataWidget<'a> {
+    index_style: Style,
+    value_style: Style,
+    untouched_style: Style,
+    pointer_style: Style,
+    changed_style: Style
+} }
 impl<'a> StatefulWidget for RuntimeDataWidget<'a> {
     type State = super::InteractiveState;
 
     fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
         let content_area = self.block.inner(area);
         self.block.render(area, buf);
+        if content_area.height < 2 || content_area.width == 0 {
+            return;
+        }
 
-        "[This feature doesn't yet exist :(]".render(content_area, buf)
+        let context = &state.runtime_context;
+        let format = state.cell_format;
+        let pointer = context.data_pointer;
+        let changed = state.changed_cell.get();
+        let tape_len = match context.tape_policy {
+            TapePolicy::Circular => Some(
+                context
+                    .max_tape_length
+                    .unwrap_or(DEFAULT_CIRCULAR_TAPE_LENGTH),
+            ),
+            _ => context.max_tape_length,
+        };
+
+        // Every cell gets the width of the widest one in view. Widening the cells can bring other
+        // cells into view, so the width is raised until it fits all of them.
+        let rows = content_area.height as usize / 2;
+        let window = |width: usize| {
+            let per_row = (content_area.width as usize + 1) / (width + 1);
+            let first_row = (pointer / per_row.max(1)).saturating_sub(rows / 2);
+            let start = first_row * per_row;
+            let end = start + rows * per_row;
+            (
+                per_row,
+                first_row,
+                start..tape_len.map_or(end, |len| end.min(len)),
+            )
+        };
+        let cell_width = |index: usize| {
+            let label = (index as isize - context.origin as isize).to_string();
+            let value = format.format(context.data.get(index).copied().unwrap_or(0));
+            label.len().max(value.chars().count())
+        };
+        let mut width = 1;
+        let (per_row, first_row) = loop {
+            let (per_row, first_row, indices) = window(width);
+            if per_row == 0 {
+                return;
+            }
+            match indices.map(cell_width).max() {
+                Some(needed) if needed > width => width = needed,
+                _ => break (per_row, first_row),
+            }
+        };
+
+        for row in 0..rows {
+            let y = content_area.y + 2 * row as u16;
+            for column in 0..per_row {
+                let index = (first_row + row) * per_row + column;
+                if tape_len.is_some_and(|len| index >= len) {
+                    return;
+                }
+                let x = content_area.x + (column * (width + 1)) as u16;
+                let value = context.data.get(index).copied();
+
+                let mut index_style = self.index_style;
+                let mut value_style = if value.is_some() {
+                    self.value_style
+                } else {
+                    self.untouched_style
+                };
+                if changed == Some(index) {
+                    value_style = value_style.patch(self.changed_style);
+                }
+                if index == pointer {
+                    index_style = index_style.patch(self.pointer_style);
+                    value_style = value_style.patch(self.pointer_style);
+                }
+
+                let value = format.format(value.unwrap_or(0));
+                let label = index as isize - context.origin as isize;
+                buf.set_string(x, y, format!("{:>width$}", label), index_style);
+                buf.set_string(x, y + 1, format!("{:>width$}", value), value_style);
+            }
+        }
     }
 }
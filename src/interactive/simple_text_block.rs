@@ -1,10 +1,9 @@
 use crate::interactive::block_widget;
 use ratatui::buffer::Buffer;
-use ratatui::layout::{Alignment, Rect};
+use ratatui::layout::Rect;
 use ratatui::prelude::Widget;
 use ratatui::text::Text;
-use ratatui::widgets::block::Title;
-use ratatui::widgets::{Block, Borders, Paragraph};
+use ratatui::widgets::{Block, Paragraph};
 
 pub struct SimpleTextBlock<'a> {
     block: Block<'a>,
@@ -0,0 +1,146 @@
+use crate::interactive::{Cell, InteractiveState};
+use brainfuck::{Breakpoint, Breakpoints, Instruction, Script};
+use std::time::Instant;
+
+/// Most instructions a snippet can run when the context has no cycle limit, so that one that
+/// never stops doesn't freeze the UI.
+pub const SNIPPET_MAX_CYCLES: usize = 1_000_000;
+
+impl InteractiveState {
+    /// Runs `snippet` to the end against the current tape, leaving the loaded script where it
+    /// is. The limits of the context apply to the snippet as if it was a script of its own, so
+    /// its cycles and time are counted from when it starts.
+    ///
+    /// Watchpoints and `#` breakpoints stop the snippet, as does reading input that hasn't been
+    /// queued yet. Breakpoints on instructions and positions are about the loaded script, so
+    /// they don't apply, which the output points out when there are any.
+    pub fn execute_snippet(&mut self, mut snippet: Script) {
+        let (hashes, ignored): (Vec<_>, Vec<_>) = self
+            .breakpoints
+            .breakpoints
+            .iter()
+            .copied()
+            .partition(|breakpoint| *breakpoint == Breakpoint::Hash);
+        if !ignored.is_empty() {
+            let ignored: Vec<_> = ignored.iter().map(ToString::to_string).collect();
+            self.cmd_info(format_args!(
+                "Ignoring breakpoints on {}, which only apply to the loaded script",
+                ignored.join(", ")
+            ));
+        }
+        let breakpoints = Breakpoints {
+            breakpoints: hashes,
+            watchpoints: self.breakpoints.watchpoints.clone(),
+        };
+        // The snippet changes the tape without leaving anything to undo.
+        self.history.clear();
+
+        let started = std::mem::replace(&mut self.runtime_context.started, Instant::now());
+        self.run_snippet(&mut snippet, &breakpoints);
+        self.runtime_context.started = started;
+    }
+
+    fn run_snippet(&mut self, snippet: &mut Script, breakpoints: &Breakpoints<Cell>) {
+        let max_cycles = self.runtime_context.max_cycles;
+        let mut executed = None;
+        loop {
+            let cycles = snippet.cycles;
+            // Checked once more after the last instruction, for the values it wrote.
+            if let Some(reason) = breakpoints.check(snippet, &self.runtime_context, executed) {
+                self.cmd_info(format_args!(
+                    "Stopped snippet at {} after {} cycles",
+                    reason, cycles
+                ));
+                return;
+            }
+            if !snippet.has_remaining_instructions() {
+                break;
+            }
+            if snippet.instruction() == Some(Instruction::AcceptData)
+                && !self.input_closed
+                && self.io.pending_input() == 0
+            {
+                self.cmd_error(format_args!(
+                    "snippet stopped waiting for input after {} cycles",
+                    cycles
+                ));
+                return;
+            }
+            if max_cycles.is_none() && cycles == SNIPPET_MAX_CYCLES {
+                self.cmd_error(format_args!(
+                    "snippet stopped after {} cycles without finishing",
+                    cycles
+                ));
+                return;
+            }
+
+            executed = snippet.loaded_instruction();
+            self.changed_cell.set(None);
+            if let Err(e) = snippet.execute_instruction(&mut self.runtime_context) {
+                let (_, column) = snippet.line_column(e.instruction.source_position);
+                self.cmd_error(format_args!(
+                    "runtime error in snippet at column {} after {} cycles: {}",
+                    column, cycles, e.kind
+                ));
+                return;
+            }
+        }
+        self.cmd_info(format_args!(
+            "Executed snippet in {} cycles",
+            snippet.cycles
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interactive::InteractiveState;
+    use brainfuck::Script;
+    use std::time::{Duration, Instant};
+
+    fn script(source: &str) -> Script {
+        Script::new(source.to_owned()).expect("script loads")
+    }
+
+    fn last_output(state: &InteractiveState) -> &str {
+        &state.command_output.last().expect("a message").message
+    }
+
+    #[test]
+    fn snippets_change_the_tape_but_not_the_script() {
+        let mut state = InteractiveState::new(script("+>+"));
+        state.execute();
+        state.execute_snippet(script(">++>+++<"));
+
+        assert_eq!(state.script.instruction_pointer, 1);
+        assert_eq!(state.script.cycles, 1);
+        assert_eq!(state.runtime_context.data, [1, 2, 3]);
+        assert_eq!(state.runtime_context.data_pointer, 1);
+        assert_eq!(last_output(&state), "Executed snippet in 8 cycles");
+
+        // The script carries on from the data pointer the snippet left.
+        state.execute();
+        state.execute();
+        assert_eq!(state.runtime_context.data, [1, 2, 4]);
+    }
+
+    #[test]
+    fn snippets_count_limits_from_their_start() {
+        let mut state = InteractiveState::new(script("+"));
+        state.script.cycles = 50;
+        state.runtime_context.max_cycles = Some(10);
+        state.runtime_context.timeout = Some(Duration::from_secs(60));
+        let started = Instant::now() - Duration::from_secs(120);
+        state.runtime_context.started = started;
+
+        state.execute_snippet(script("+++++"));
+        assert_eq!(last_output(&state), "Executed snippet in 5 cycles");
+        assert_eq!(state.runtime_context.started, started);
+
+        state.execute_snippet(script("+[]"));
+        assert_eq!(
+            last_output(&state),
+            "Error: runtime error in snippet at column 3 after 10 cycles: ran for more than 10 cycles"
+        );
+    }
+}
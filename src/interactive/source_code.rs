@@ -1,11 +1,10 @@
 use std::borrow::Cow;
-use std::ops::Div;
 
 use ratatui::prelude::*;
 use ratatui::widgets::Paragraph;
 
-use crate::interactive::{styles, widget_setter, InteractiveState};
-use crate::Instruction;
+use crate::interactive::widget_setter;
+use brainfuck::Instruction;
 
 pub struct SourceCode<'a> {
     current_instruction_style: Style,
@@ -45,14 +44,6 @@ impl<'a> Widget for SourceCode<'a> {
         let mut spans = vec![];
         let mut span_start = 0;
         let mut span_is_instruction = false;
-        let mut line_offset = 0;
-        let amount_of_lines = self.code.lines().count();
-        let viewable_area_y_size = { area.height as f64 * 0.8 }.ceil() as usize;
-        let viewable_areas_y = if viewable_area_y_size == 0 {
-            1
-        } else {
-            amount_of_lines.div_ceil(viewable_area_y_size)
-        };
 
         for (i, ch) in self.code.char_indices() {
             let current_span_style = if span_is_instruction {
@@ -65,10 +56,6 @@ impl<'a> Widget for SourceCode<'a> {
                     spans.push(Span::styled(&self.code[span_start..i], current_span_style));
                     spans.push(Span::styled(ch.to_string(), self.current_instruction_style));
                     span_start = i + 1;
-                    if lines.len() >= viewable_area_y_size {
-                        let area = lines.len().div(viewable_area_y_size);
-                        line_offset += viewable_area_y_size * area;
-                    }
                     continue;
                 }
             }
@@ -87,7 +74,7 @@ impl<'a> Widget for SourceCode<'a> {
                 lines.push(line);
                 continue;
             }
-            let should_be_instruction = Instruction::from_char(ch) != None;
+            let should_be_instruction = Instruction::from_char(ch).is_some();
             if span_is_instruction != should_be_instruction {
                 spans.push(Span::styled(&self.code[span_start..i], current_span_style));
                 span_start = i;
@@ -0,0 +1,8 @@
+//! A Brainf*ck interpreter and compiler.
+//!
+//! Scripts are loaded with [`Script::new`] and run on a [`RuntimeContext`],
+//! which is set up with [`RuntimeContext::builder`].
+pub mod compile;
+pub mod runtime;
+
+pub use runtime::*;
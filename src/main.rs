@@ -1,32 +1,127 @@
 mod interactive;
-pub mod runtime;
-
-pub use runtime::*;
 
 use crate::interactive::interactive_runtime;
+use brainfuck::*;
 use clap::Parser;
 use crossterm::execute;
 use crossterm::terminal::{
     disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
 };
-use ratatui::layout::Constraint::Length;
-use ratatui::prelude::Constraint::Min;
+use num_bigint::BigInt;
 use ratatui::prelude::*;
-use ratatui::widgets::Block;
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::error::Error;
+use std::fmt::Debug;
+use std::fs::{self, File};
 use std::io;
-use std::io::{stdin, stdout, Read, Write};
-use std::time::Duration;
+use std::io::{stdin, stdout, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 #[derive(clap::Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct Cli {
+    #[command(subcommand)]
+    command: Option<CliCommand>,
     #[arg(long, value_enum, default_value_t)]
     mode: Mode,
+    /// Execution engine to use. `auto` picks `step` in debug mode, and otherwise `jit` when
+    /// it supports the script's settings and `bytecode` if not.
+    #[arg(long, value_enum, default_value_t)]
+    engine: Engine,
+    /// What the tape looks like at its edges (left-bounded, bidirectional or circular).
+    #[arg(long, default_value_t)]
+    tape: TapePolicy,
+    /// Maximum number of cells on the tape. For circular tapes, this is the length of the tape.
+    #[arg(long)]
+    max_tape_length: Option<usize>,
+    /// Maximum number of cycles to run for.
+    #[arg(long)]
+    max_cycles: Option<usize>,
+    /// Maximum time to run for, e.g. `2s` or `500ms`.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    timeout: Option<Duration>,
+    /// Maximum number of bytes of output.
+    #[arg(long)]
+    max_output: Option<usize>,
+    /// What happens when a cell goes past its bounds (wrap, saturate or trap).
+    #[arg(long, default_value_t)]
+    overflow: OverflowMode,
+    /// What to do when reading past the end of input (zero, minus-one, unchanged or error).
+    #[arg(long, default_value_t)]
+    eof: EofBehaviour,
+    /// Cell type (u8, u16, u32, u64, i8, i16, i32, i64 or bignum).
+    #[arg(long, default_value_t)]
+    cell: RuntimeCellKind,
+    /// Show the tape when the script gets to a breakpoint in debug mode: `#` for every `#` in the
+    /// source, `@<position>` for a position in the source or an instruction index.
+    #[arg(long = "break", value_name = "BREAKPOINT")]
+    breakpoints: Vec<Breakpoint>,
+    /// Show the tape when a cell is used in debug mode: `<cell>:read`, `<cell>:write` or
+    /// `<cell>=<value>` for when it's set to a value.
+    #[arg(long, value_name = "WATCHPOINT")]
+    watch: Vec<String>,
+    /// Save a snapshot of the running script to this file every `--checkpoint-interval`, and
+    /// when a limit stops it. Checkpointed scripts run with the `step` engine.
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+    /// How often to save checkpoints, e.g. `30s` or `5m`.
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "1m")]
+    checkpoint_interval: Duration,
+    /// Resume the script saved in a snapshot instead of loading one. Its input, tape and
+    /// settings come from the snapshot, and it runs with the `step` engine.
+    #[arg(long, conflicts_with_all = ["stdin", "code"])]
+    resume: Option<PathBuf>,
     #[arg(long)]
     stdin: bool,
     code: Option<String>,
 }
 
+#[derive(clap::Subcommand)]
+enum CliCommand {
+    /// Compile a script ahead of time instead of running it.
+    Compile(CompileArgs),
+}
+
+#[derive(clap::Args)]
+struct CompileArgs {
+    #[arg(long, value_enum, default_value_t)]
+    target: Target,
+    /// File to write the result to. Defaults to stdout.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Cell type (u8, u16, u32 or u64).
+    #[arg(long, default_value_t)]
+    cell: CellKind,
+    /// Number of cells on the tape.
+    #[arg(long, default_value_t = 30000)]
+    tape_size: usize,
+    /// What to do when reading past the end of input (zero, minus-one, unchanged or error).
+    #[arg(long, default_value_t)]
+    eof: EofBehaviour,
+    /// Script to compile, or `-` to read it from stdin.
+    file: PathBuf,
+}
+
+#[derive(clap::ValueEnum, Copy, Clone, Default, Eq, PartialEq)]
+enum Target {
+    /// A static x86-64 Linux executable.
+    #[default]
+    Elf,
+    /// A self-contained C program.
+    C,
+    /// A Rust module with a `run` function.
+    Rust,
+    /// A Rust `main.rs` that runs the script on stdin and stdout.
+    RustMain,
+    /// A WebAssembly module that imports its I/O functions.
+    Wasm,
+    /// A WebAssembly module in the text format.
+    Wat,
+}
+
 #[derive(clap::ValueEnum, Copy, Clone, Default, Eq, PartialEq)]
 enum Mode {
     #[default]
@@ -36,58 +131,285 @@ enum Mode {
     Interactive,
 }
 
+#[derive(clap::ValueEnum, Copy, Clone, Default, Eq, PartialEq)]
+enum Engine {
+    #[default]
+    Auto,
+    /// Executes one instruction at a time.
+    Step,
+    /// Executes the optimised IR directly.
+    Ir,
+    /// Compiles the optimised IR to bytecode and runs it in a virtual machine.
+    Bytecode,
+    /// Compiles the optimised IR to machine code at runtime.
+    /// Falls back to `bytecode` when built without the `jit` feature.
+    Jit,
+}
+
 fn main() {
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
+
+    if let Some(CliCommand::Compile(args)) = cli.command.take() {
+        compile(args);
+        return;
+    }
+
+    if cli.mode == Mode::Interactive && cli.resume.is_some() {
+        eprintln!("Snapshots can't be resumed in interactive mode, use the `restore` command");
+        std::process::exit(1);
+    }
+
+    if cli.mode != Mode::Debug && !(cli.breakpoints.is_empty() && cli.watch.is_empty()) {
+        eprintln!("Breakpoints only work in debug mode, add `--mode debug`");
+        std::process::exit(1);
+    }
 
     let mut code;
-    if cli.mode == Mode::Interactive {
+    if cli.mode == Mode::Interactive || cli.resume.is_some() {
+        // Resumed scripts are loaded from their snapshot later, once the cell type is known.
         code = String::new();
     } else if cli.stdin {
         code = String::new();
-        stdin()
-            .read_to_string(&mut code)
-            .expect("Could not read from stdin");
-    } else if let Some(code_opt) = cli.code {
+        if let Err(e) = stdin().read_to_string(&mut code) {
+            eprintln!("Could not read script from stdin: {}", e);
+            std::process::exit(1);
+        }
+    } else if let Some(code_opt) = cli.code.take() {
         code = code_opt;
     } else {
         code = String::new();
-        stdin()
-            .read_line(&mut code)
-            .expect("Could not read line from stdin");
+        if let Err(e) = stdin().read_line(&mut code) {
+            eprintln!("Could not read script from stdin: {}", e);
+            std::process::exit(1);
+        }
     }
 
+    let mut runtime = match Script::new(code) {
+        Ok(script) => script,
+        Err(e) => {
+            eprintln!("Could not load script: {}", e);
+            std::process::exit(1);
+        }
+    };
+
     if cli.mode == Mode::Interactive {
-        interactive(Script::new(code)).expect("Failure");
+        if let Err(e) = interactive(runtime) {
+            eprintln!("Interactive mode failed: {}", e);
+            std::process::exit(1);
+        }
         return;
     }
 
-    let mut runtime = Script::new(code);
-    let mut context = RuntimeContextU8::new_stdio();
-    context.refresh_fn = Some(Box::new(|script, context| {
-        let instruction = if let Some(instr) = script.instruction() {
-            format!("{:?}", instr)
-        } else {
-            format!(
-                "<end+{}>",
-                script.instructions.len() - script.instruction_pointer
-            )
-        };
-        println!(
-            "{}: data(*{}={}) instr(*{}={})",
-            script.cycles,
-            context.data_pointer,
-            context.read_cell(context.data_pointer),
-            script.instruction_pointer,
-            instruction
-        )
-    }));
-    while runtime.has_remaining_instructions() {
-        runtime.execute_instruction(&mut context);
-        if runtime.cycles % 20 == 9 {
-            stdout().flush().expect("Could not flush");
+    let result = match cli.cell {
+        RuntimeCellKind::U8 => run::<u8>(&cli, &mut runtime, jit_supports, run_jit),
+        RuntimeCellKind::U16 => run::<u16>(&cli, &mut runtime, jit_supports, run_jit),
+        RuntimeCellKind::U32 => run::<u32>(&cli, &mut runtime, jit_supports, run_jit),
+        RuntimeCellKind::U64 => run::<u64>(&cli, &mut runtime, jit_supports, run_jit),
+        RuntimeCellKind::I8 => run::<i8>(&cli, &mut runtime, jit_supports, run_jit),
+        RuntimeCellKind::I16 => run::<i16>(&cli, &mut runtime, jit_supports, run_jit),
+        RuntimeCellKind::I32 => run::<i32>(&cli, &mut runtime, jit_supports, run_jit),
+        RuntimeCellKind::I64 => run::<i64>(&cli, &mut runtime, jit_supports, run_jit),
+        RuntimeCellKind::Bignum => run::<BigInt>(&cli, &mut runtime, |_| false, run_jit_bignum),
+    };
+
+    if let Err(e) = result {
+        let (line, column) = runtime.line_column(e.instruction.source_position);
+        eprint!("Runtime error at line {}, column {}", line, column);
+        if let Some(cycles) = e.cycles {
+            eprint!(" after {} cycles", cycles);
+        }
+        eprintln!(": {}", e.kind);
+        std::process::exit(1);
+    }
+}
+
+fn compile(args: CompileArgs) {
+    let code = if args.file.as_os_str() == "-" {
+        let mut code = String::new();
+        stdin()
+            .read_to_string(&mut code)
+            .map(|_| code)
+            .map_err(|e| e.to_string())
+    } else {
+        std::fs::read_to_string(&args.file).map_err(|e| format!("{}: {}", args.file.display(), e))
+    };
+    let code = code.unwrap_or_else(|e| {
+        eprintln!("Could not read script: {}", e);
+        std::process::exit(1);
+    });
+    let script = Script::new(code).unwrap_or_else(|e| {
+        eprintln!("Could not load script: {}", e);
+        std::process::exit(1);
+    });
+
+    // Compiled programs always wrap around, and stop if the pointer leaves the tape.
+    let program = ir::Program::optimise(&script, OverflowMode::Wrap, TapePolicy::LeftBounded);
+    let options = compile::CompileOptions {
+        cell: args.cell,
+        tape_size: args.tape_size,
+        eof: args.eof,
+    };
+    let output = match args.target {
+        Target::Elf => compile::elf::generate(&program, &options).unwrap_or_else(|e| {
+            eprintln!("Could not compile script: {}", e);
+            std::process::exit(1);
+        }),
+        Target::C => compile::c::generate(&program, &options).into_bytes(),
+        Target::Rust => compile::rust::generate(&program, &options, false).into_bytes(),
+        Target::RustMain => compile::rust::generate(&program, &options, true).into_bytes(),
+        Target::Wasm => compile::wasm::generate_binary(&program, &options),
+        Target::Wat => compile::wasm::generate_text(&program, &options).into_bytes(),
+    };
+
+    let result = match &args.output {
+        Some(path) => std::fs::write(path, output).and_then(|_| {
+            if args.target == Target::Elf {
+                make_executable(path)
+            } else {
+                Ok(())
+            }
+        }),
+        None => stdout().write_all(&output),
+    };
+    if let Err(e) = result {
+        eprintln!("Could not write output: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(unix)]
+fn make_executable(path: &std::path::Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o111);
+    std::fs::set_permissions(path, permissions)
+}
+#[cfg(not(unix))]
+fn make_executable(_path: &std::path::Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Whether the `jit` engine supports a context.
+type JitCheck<T> = fn(&RuntimeContext<T>) -> bool;
+/// Runs an optimised program for the `jit` engine.
+type JitRunner<T> = fn(&ir::Program, &mut RuntimeContext<T>) -> Result<(), RuntimeError>;
+
+/// Runs a script on stdio with cells of type `T`, printing a dump afterwards if the mode asks
+/// for one.
+fn run<T: CellType + Debug>(
+    cli: &Cli,
+    runtime: &mut Script,
+    jit_supports: JitCheck<T>,
+    run_jit: JitRunner<T>,
+) -> Result<(), RuntimeError> {
+    // Checkpointed scripts keep their input and output in memory, so that snapshots can hold
+    // them. The input is read up front, since stdin can't be put back when resuming.
+    let checkpointed = cli.checkpoint.is_some() || cli.resume.is_some();
+    let io = Rc::new(RefCell::new(RecordedIo::default()));
+    let snapshot = cli.resume.as_deref().map(|path| {
+        let (snapshot, script) = load_snapshot::<T>(path);
+        *runtime = script;
+        snapshot
+    });
+    if let Some(snapshot) = &snapshot {
+        let mut io = io.borrow_mut();
+        io.input = snapshot.input.iter().copied().collect();
+        io.output = snapshot.output.clone();
+    } else if checkpointed {
+        let mut input = Vec::new();
+        if let Err(e) = stdin().read_to_end(&mut input) {
+            eprintln!("Could not read input from stdin: {}", e);
+            std::process::exit(1);
+        }
+        io.borrow_mut().input = input.into();
+    }
+
+    let builder = RuntimeContext::<T>::builder()
+        .tape_policy(cli.tape)
+        .max_tape_length(cli.max_tape_length)
+        .max_cycles(cli.max_cycles)
+        .timeout(cli.timeout)
+        .max_output(cli.max_output)
+        .overflow_mode(cli.overflow)
+        .eof_behaviour(cli.eof);
+    let builder = if checkpointed {
+        builder
+            .read_with({
+                let io = io.clone();
+                move || Ok(io.borrow_mut().input.pop_front().map(T::from_u8))
+            })
+            .write_with({
+                let io = io.clone();
+                move |value| {
+                    let bytes = value.as_u8_array();
+                    stdout().write_all(&bytes)?;
+                    io.borrow_mut().output.extend_from_slice(&bytes);
+                    Ok(())
+                }
+            })
+    } else {
+        builder.stdio()
+    };
+    let mut context = builder.build();
+    if let Some(snapshot) = &snapshot {
+        snapshot.restore(&mut context);
+    }
+    let mut breakpoints = Breakpoints::<T>::default();
+    breakpoints.breakpoints = cli.breakpoints.clone();
+    for watchpoint in &cli.watch {
+        match watchpoint.parse() {
+            Ok(watchpoint) => breakpoints.watchpoints.push(watchpoint),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
         }
     }
-    stdout().flush().expect("Could not flush");
+    let needs_step = checkpointed || !breakpoints.is_empty();
+    let engine = match cli.engine {
+        Engine::Auto | Engine::Step if needs_step => Engine::Step,
+        _ if needs_step => {
+            eprintln!(
+                "Checkpoints and breakpoints only work with the step engine, using it instead"
+            );
+            Engine::Step
+        }
+        Engine::Auto if cli.mode == Mode::Debug => Engine::Step,
+        Engine::Auto if jit_supports(&context) => Engine::Jit,
+        Engine::Auto => Engine::Bytecode,
+        engine => engine,
+    };
+    // Unbounded cells never wrap around, so they can only be optimised like trapping cells.
+    let overflow_mode = if T::BITS.is_some() {
+        context.overflow_mode
+    } else {
+        OverflowMode::Trap
+    };
+    let tape_policy = context.tape_policy;
+    let result = match engine {
+        Engine::Auto => unreachable!("auto engine should have been resolved"),
+        Engine::Step => {
+            let checkpoints = cli.checkpoint.as_deref().map(|path| Checkpoints {
+                path,
+                interval: cli.checkpoint_interval,
+                io: &io,
+            });
+            let trace = cli.mode == Mode::Debug;
+            run_step(runtime, &mut context, trace, &breakpoints, checkpoints)
+        }
+        Engine::Ir => ir::Program::optimise(runtime, overflow_mode, tape_policy).run(&mut context),
+        Engine::Bytecode => {
+            let program = ir::Program::optimise(runtime, overflow_mode, tape_policy);
+            run_bytecode(&program, &mut context)
+        }
+        Engine::Jit => {
+            let program = ir::Program::optimise(runtime, overflow_mode, tape_policy);
+            run_jit(&program, &mut context)
+        }
+    };
+    // Output that can't be flushed any more has nowhere else to go, so it's dropped.
+    let _ = stdout().flush();
+    context.trim_tape();
 
     match cli.mode {
         Mode::Dump | Mode::Debug => {
@@ -100,8 +422,195 @@ fn main() {
                 &context.data
             )
         }
+        // When an error stops the script, show how far it got.
+        _ if result.is_err() => {
+            eprintln!("Tape when the script was stopped: {:?}", &context.data)
+        }
         _ => {}
     }
+    result
+}
+
+fn run_step<T: CellType + Debug>(
+    runtime: &mut Script,
+    context: &mut RuntimeContext<T>,
+    trace: bool,
+    breakpoints: &Breakpoints<T>,
+    checkpoints: Option<Checkpoints>,
+) -> Result<(), RuntimeError> {
+    if trace {
+        context.add_observer(Tracer);
+    }
+    if breakpoints.is_empty() && checkpoints.is_none() {
+        runtime.run(context)?;
+        return Ok(());
+    }
+    let mut last_saved = Instant::now();
+    let mut executed = None;
+    loop {
+        // Debug mode can't wait for anyone to continue, so breaking only shows the tape.
+        if let Some(reason) = breakpoints.check(runtime, context, executed) {
+            println!("--- BREAK ({}) ---\n{:?}", reason, &context.data);
+        }
+        if !runtime.has_remaining_instructions() {
+            return Ok(());
+        }
+        executed = runtime.loaded_instruction();
+        if let Err(e) = runtime.execute_instruction(context) {
+            // Scripts stopped by a limit can carry on with a higher one, so they're worth
+            // resuming. Other errors would only happen again.
+            if let Some(checkpoints) = &checkpoints {
+                if e.kind.is_limit() && checkpoints.save(runtime, context) {
+                    eprintln!("Saved checkpoint to {}", checkpoints.path.display());
+                }
+            }
+            return Err(e);
+        }
+        if let Some(checkpoints) = &checkpoints {
+            if runtime.cycles.is_multiple_of(TIME_CHECK_INTERVAL)
+                && last_saved.elapsed() >= checkpoints.interval
+            {
+                checkpoints.save(runtime, context);
+                last_saved = Instant::now();
+            }
+        }
+    }
+}
+
+/// Input and output of a checkpointed script.
+#[derive(Default)]
+struct RecordedIo {
+    /// Input that hasn't been read yet.
+    input: VecDeque<u8>,
+    /// Everything written so far.
+    output: Vec<u8>,
+}
+
+/// Where and how often the step engine saves snapshots of a script.
+struct Checkpoints<'a> {
+    path: &'a Path,
+    interval: Duration,
+    io: &'a RefCell<RecordedIo>,
+}
+impl Checkpoints<'_> {
+    /// Saves a snapshot, returning whether it worked.
+    /// The snapshot is written next to the checkpoint and then moved over it, so that a run
+    /// killed while saving still leaves the previous checkpoint behind.
+    fn save<T: CellType>(&self, runtime: &Script, context: &RuntimeContext<T>) -> bool {
+        let io = self.io.borrow();
+        let input = io.input.iter().copied().collect();
+        let snapshot = Snapshot::capture(runtime, context, input, io.output.clone());
+        let mut temporary = self.path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        let result = File::create(&temporary)
+            .and_then(|file| snapshot.write_to(BufWriter::new(file)))
+            .and_then(|_| fs::rename(&temporary, self.path));
+        if let Err(e) = &result {
+            eprintln!(
+                "Could not save checkpoint to {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+        result.is_ok()
+    }
+}
+
+/// Loads a snapshot and its script to resume, or exits if that fails.
+fn load_snapshot<T: CellType>(path: &Path) -> (Snapshot<T>, Script) {
+    let loaded = File::open(path)
+        .map_err(SnapshotError::from)
+        .and_then(Snapshot::read_from)
+        .and_then(|snapshot| Ok((snapshot.script()?, snapshot)));
+    match loaded {
+        Ok((script, snapshot)) => (snapshot, script),
+        Err(e) => {
+            eprintln!("Could not resume from {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run_bytecode<T: CellType>(
+    program: &ir::Program,
+    context: &mut RuntimeContext<T>,
+) -> Result<(), RuntimeError> {
+    match bytecode::Bytecode::compile(program) {
+        Ok(bytecode) => bytecode.run(context),
+        Err(e) => {
+            eprintln!("Could not compile to bytecode, using ir instead: {}", e);
+            program.run(context)
+        }
+    }
+}
+
+#[cfg(feature = "jit")]
+fn run_jit<T: CellType + Copy>(
+    program: &ir::Program,
+    context: &mut RuntimeContext<T>,
+) -> Result<(), RuntimeError> {
+    match jit::JitProgram::compile(program, context) {
+        Ok(compiled) => compiled.run(context),
+        Err(e) => {
+            eprintln!(
+                "Could not JIT-compile script, using bytecode instead: {}",
+                e
+            );
+            run_bytecode(program, context)
+        }
+    }
+}
+#[cfg(not(feature = "jit"))]
+fn run_jit<T: CellType>(
+    program: &ir::Program,
+    context: &mut RuntimeContext<T>,
+) -> Result<(), RuntimeError> {
+    eprintln!("Built without the `jit` feature, using bytecode instead");
+    run_bytecode(program, context)
+}
+
+#[cfg(feature = "jit")]
+fn jit_supports<T: CellType + Copy>(context: &RuntimeContext<T>) -> bool {
+    jit::JitProgram::check_support(context).is_ok()
+}
+#[cfg(not(feature = "jit"))]
+fn jit_supports<T: CellType>(_context: &RuntimeContext<T>) -> bool {
+    false
+}
+fn run_jit_bignum(
+    program: &ir::Program,
+    context: &mut RuntimeContext<BigInt>,
+) -> Result<(), RuntimeError> {
+    eprintln!("Bignum cells can't be JIT-compiled, using bytecode instead");
+    run_bytecode(program, context)
+}
+
+/// Prints every instruction as it runs in debug mode.
+struct Tracer;
+impl<T: CellType + Debug> ExecutionObserver<T> for Tracer {
+    fn instruction_executed(
+        &mut self,
+        script: &Script,
+        context: &RuntimeContext<T>,
+        _instruction: LoadedInstruction,
+    ) {
+        let instruction = if let Some(instr) = script.instruction() {
+            format!("{:?}", instr)
+        } else {
+            format!(
+                "<end+{}>",
+                script.instructions.len() - script.instruction_pointer
+            )
+        };
+        println!(
+            "{}: data(*{}={:?}) instr(*{}={})",
+            script.cycles - 1,
+            context.data_pointer,
+            context.read_cell(context.data_pointer),
+            script.instruction_pointer,
+            instruction
+        )
+    }
 }
 
 fn interactive(runtime: Script) -> Result<(), Box<dyn Error>> {
@@ -1,11 +1,37 @@
+pub use crate::runtime::breakpoints::*;
+pub use crate::runtime::builder::*;
 pub use crate::runtime::context::*;
+pub use crate::runtime::observer::*;
+pub use crate::runtime::settings::*;
+pub use crate::runtime::snapshot::*;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
 
+mod breakpoints;
+mod builder;
+pub mod bytecode;
 mod context;
+#[cfg(test)]
+mod engine_tests;
+pub mod ir;
+#[cfg(feature = "jit")]
+pub mod jit;
+mod observer;
+mod settings;
+mod snapshot;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 pub struct LoadedInstruction {
     pub instruction: Instruction,
     pub source_position: usize,
+    /// Index of the matching bracket, if this instruction is a bracket.
+    pub partner: Option<usize>,
+    /// Whether there's a `#` between this instruction and the one before it.
+    /// Debuggers can treat these as breakpoints.
+    pub follows_hash: bool,
 }
 
 pub struct Script {
@@ -28,6 +54,18 @@ pub enum Instruction {
 }
 
 impl Instruction {
+    pub fn as_char(self) -> char {
+        match self {
+            Instruction::IncrementDataPointer => '>',
+            Instruction::DecrementDataPointer => '<',
+            Instruction::IncrementData => '+',
+            Instruction::DecrementData => '-',
+            Instruction::OutputData => '.',
+            Instruction::AcceptData => ',',
+            Instruction::JumpForwardsIfZero => '[',
+            Instruction::JumpBackwardsIfNonzero => ']',
+        }
+    }
     pub fn from_char(ch: char) -> Option<Self> {
         Some(match ch {
             '>' => Instruction::IncrementDataPointer,
@@ -43,103 +81,410 @@ impl Instruction {
     }
 }
 
+/// A bracket without a partner, found while loading a script.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct UnmatchedBracket {
+    pub instruction: Instruction,
+    pub source_position: usize,
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column, counted in characters.
+    pub column: usize,
+}
+impl Display for UnmatchedBracket {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "unmatched '{}' at line {}, column {} (position {})",
+            self.instruction.as_char(),
+            self.line,
+            self.column,
+            self.source_position
+        )
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ParseError {
+    /// Every unmatched bracket, in source order.
+    pub unmatched: Vec<UnmatchedBracket>,
+}
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, bracket) in self.unmatched.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            Display::fmt(bracket, f)?;
+        }
+        Ok(())
+    }
+}
+impl Error for ParseError {}
+
+/// What happened after executing an instruction.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum StepOutcome {
+    /// There are more instructions to execute.
+    Continue,
+    /// The script has no instructions left.
+    Finished,
+}
+
+/// An error that stopped a script while it was running.
+#[derive(Clone, Debug)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    /// The instruction that failed.
+    /// The optimised engines report the first instruction of the operation that failed.
+    pub instruction: LoadedInstruction,
+    /// Number of instructions executed before the error, if the engine counts them.
+    /// Only the step engine does.
+    pub cycles: Option<usize>,
+}
+#[derive(Clone, Debug)]
+pub enum RuntimeErrorKind {
+    Tape(TapeError),
+    /// A cell went past its bounds while the overflow mode is [`OverflowMode::Trap`].
+    /// The instruction is the `+` or `-` that changed the cell.
+    Overflow(Instruction),
+    /// Input was read after its end while the EOF behaviour is [`EofBehaviour::Error`].
+    EndOfInput,
+    /// Reading input or writing output failed.
+    Io(Arc<io::Error>),
+    /// The script ran for more than [`RuntimeContext::max_cycles`] cycles.
+    CycleLimit {
+        limit: usize,
+    },
+    /// The script ran for longer than [`RuntimeContext::timeout`].
+    TimeLimit {
+        limit: Duration,
+    },
+    /// The script wrote more than [`RuntimeContext::max_output`] bytes of output.
+    OutputLimit {
+        limit: usize,
+    },
+}
+impl RuntimeError {
+    pub fn new(
+        kind: RuntimeErrorKind,
+        instruction: LoadedInstruction,
+        cycles: Option<usize>,
+    ) -> Self {
+        Self {
+            kind,
+            instruction,
+            cycles,
+        }
+    }
+}
+impl RuntimeErrorKind {
+    /// Whether the error comes from one of the limits set on the context, rather than from
+    /// something the script did wrong.
+    pub fn is_limit(&self) -> bool {
+        matches!(
+            self,
+            RuntimeErrorKind::Tape(TapeError::LimitExceeded { .. })
+                | RuntimeErrorKind::CycleLimit { .. }
+                | RuntimeErrorKind::TimeLimit { .. }
+                | RuntimeErrorKind::OutputLimit { .. }
+        )
+    }
+    /// An overflow caused by adding `amount` to a cell.
+    pub fn overflow(amount: i128) -> Self {
+        if amount < 0 {
+            Self::Overflow(Instruction::DecrementData)
+        } else {
+            Self::Overflow(Instruction::IncrementData)
+        }
+    }
+}
+impl From<TapeError> for RuntimeErrorKind {
+    fn from(error: TapeError) -> Self {
+        Self::Tape(error)
+    }
+}
+impl From<io::Error> for RuntimeErrorKind {
+    fn from(error: io::Error) -> Self {
+        Self::Io(Arc::new(error))
+    }
+}
+impl From<OutputError> for RuntimeErrorKind {
+    fn from(error: OutputError) -> Self {
+        match error {
+            OutputError::LimitExceeded { limit } => Self::OutputLimit { limit },
+            OutputError::Io(error) => error.into(),
+        }
+    }
+}
+impl From<InputError> for RuntimeErrorKind {
+    fn from(error: InputError) -> Self {
+        match error {
+            InputError::EndOfInput => Self::EndOfInput,
+            InputError::Io(error) => error.into(),
+        }
+    }
+}
+impl Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuntimeErrorKind::Tape(error) => Display::fmt(error, f),
+            RuntimeErrorKind::Overflow(Instruction::DecrementData) => {
+                f.write_str("'-' took the cell below its minimum value")
+            }
+            RuntimeErrorKind::Overflow(instruction) => write!(
+                f,
+                "'{}' took the cell above its maximum value",
+                instruction.as_char()
+            ),
+            RuntimeErrorKind::EndOfInput => f.write_str("read past the end of input"),
+            RuntimeErrorKind::Io(error) => write!(f, "I/O error: {}", error),
+            RuntimeErrorKind::CycleLimit { limit } => {
+                write!(f, "ran for more than {} cycles", limit)
+            }
+            RuntimeErrorKind::TimeLimit { limit } => {
+                write!(
+                    f,
+                    "ran for longer than {}",
+                    humantime::format_duration(*limit)
+                )
+            }
+            RuntimeErrorKind::OutputLimit { limit } => {
+                write!(f, "wrote more than {} bytes of output", limit)
+            }
+        }
+    }
+}
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at '{}' (position {})",
+            self.kind,
+            self.instruction.instruction.as_char(),
+            self.instruction.source_position
+        )?;
+        if let Some(cycles) = self.cycles {
+            write!(f, " after {} cycles", cycles)?;
+        }
+        Ok(())
+    }
+}
+impl Error for RuntimeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            RuntimeErrorKind::Tape(error) => Some(error),
+            RuntimeErrorKind::Io(error) => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
 impl Script {
-    pub fn new(source: String) -> Self {
-        let mut instructions = Vec::new();
+    pub fn new(source: String) -> Result<Self, ParseError> {
+        let mut instructions: Vec<LoadedInstruction> = Vec::new();
+        let mut open_brackets = Vec::new();
+        let mut unmatched = Vec::new();
+        let (mut line, mut column) = (1, 1);
+        let mut follows_hash = false;
         for (u, ch) in source.chars().enumerate() {
+            let position = (line, column);
+            if ch == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
             let Some(instruction) = Instruction::from_char(ch) else {
+                follows_hash |= ch == '#';
                 continue;
             };
+            let idx = instructions.len();
+            let mut partner = None;
+            match instruction {
+                Instruction::JumpForwardsIfZero => open_brackets.push((idx, position)),
+                Instruction::JumpBackwardsIfNonzero => {
+                    if let Some((open, _)) = open_brackets.pop() {
+                        instructions[open].partner = Some(idx);
+                        partner = Some(open);
+                    } else {
+                        unmatched.push(UnmatchedBracket {
+                            instruction,
+                            source_position: u,
+                            line: position.0,
+                            column: position.1,
+                        });
+                    }
+                }
+                _ => {}
+            }
             instructions.push(LoadedInstruction {
                 instruction,
                 source_position: u,
+                partner,
+                follows_hash,
             });
+            follows_hash = false;
         }
-        Self {
+        for (idx, (line, column)) in open_brackets {
+            unmatched.push(UnmatchedBracket {
+                instruction: Instruction::JumpForwardsIfZero,
+                source_position: instructions[idx].source_position,
+                line,
+                column,
+            });
+        }
+        if !unmatched.is_empty() {
+            unmatched.sort_by_key(|v| v.source_position);
+            return Err(ParseError { unmatched });
+        }
+        Ok(Self {
             source,
             instructions,
             instruction_pointer: 0,
             cycles: 0,
-        }
+        })
     }
 
-    pub fn jump_forwards<T: CellType>(&mut self, context: &RuntimeContext<T>) -> bool {
-        let mut depth = 0usize;
-        while self.instruction_pointer < self.instructions.len() {
-            self.instruction_pointer += 1;
-            context.refresh(self);
-            let Some(instruction) = self.instruction() else {
-                break;
-            };
-            if instruction == Instruction::JumpBackwardsIfNonzero {
-                if depth == 0 {
-                    return true;
-                }
-                depth -= 1;
-            }
-            if instruction == Instruction::JumpForwardsIfZero {
-                depth += 1;
-            }
-        }
-        return false;
+    /// Moves the instruction pointer to the `]` matching the current `[`.
+    pub fn jump_forwards(&mut self) -> bool {
+        let Some(partner) = self.loaded_instruction().and_then(|v| v.partner) else {
+            return false;
+        };
+        self.instruction_pointer = partner;
+        true
     }
 
-    pub fn jump_backwards<T: CellType>(&mut self, context: &RuntimeContext<T>) -> bool {
-        let mut depth = 0usize;
-        while self.instruction_pointer > 0 {
-            self.instruction_pointer -= 1;
-            context.refresh(self);
-            let Some(instruction) = self.instruction() else {
-                break;
-            };
-            if instruction == Instruction::JumpForwardsIfZero {
-                if depth == 0 {
-                    self.instruction_pointer += 1;
-                    return true;
-                }
-                depth -= 1;
+    /// Moves the instruction pointer to just after the `[` matching the current `]`.
+    pub fn jump_backwards(&mut self) -> bool {
+        let Some(partner) = self.loaded_instruction().and_then(|v| v.partner) else {
+            return false;
+        };
+        self.instruction_pointer = partner + 1;
+        true
+    }
+
+    /// Executes the current instruction, sending its events to the observers of the context.
+    /// If it fails, the instruction pointer stays on it and no cycle is counted.
+    pub fn execute_instruction<T: CellType>(
+        &mut self,
+        context: &mut RuntimeContext<T>,
+    ) -> Result<StepOutcome, RuntimeError> {
+        let Some(loaded) = self.loaded_instruction() else {
+            return Ok(StepOutcome::Finished);
+        };
+        let result = self.execute_loaded(loaded, context);
+        match &result {
+            Ok(StepOutcome::Continue) => {}
+            Ok(StepOutcome::Finished) => {
+                context.notify(|observer, context| observer.halted(self, context, None))
             }
-            if instruction == Instruction::JumpBackwardsIfNonzero {
-                depth += 1;
+            Err(error) => {
+                context.notify(|observer, context| observer.halted(self, context, Some(error)))
             }
         }
-        return false;
+        result
     }
 
-    pub fn execute_instruction<T: CellType>(&mut self, context: &mut RuntimeContext<T>) {
-        let Some(instruction) = self.instruction() else {
-            return;
-        };
+    fn execute_loaded<T: CellType>(
+        &mut self,
+        loaded: LoadedInstruction,
+        context: &mut RuntimeContext<T>,
+    ) -> Result<StepOutcome, RuntimeError> {
+        let cycles = self.cycles;
+        let error = |kind| RuntimeError::new(kind, loaded, Some(cycles));
+        context.check_limits(cycles).map_err(error)?;
+        let pointer = context.data_pointer;
+        let writes_cell = matches!(
+            loaded.instruction,
+            Instruction::IncrementData | Instruction::DecrementData | Instruction::AcceptData
+        );
+        // Cells can be expensive to clone, so this is only done when someone is watching.
+        let old_cell = (writes_cell && context.has_observers()).then(|| context.read_cell(pointer));
         let mut next_instr = true;
-        match instruction {
-            Instruction::IncrementDataPointer => context.data_pointer += 1,
-            Instruction::DecrementDataPointer => context.data_pointer -= 1,
-            Instruction::IncrementData => context.increment_cell(context.data_pointer),
-            Instruction::DecrementData => context.decrement_cell(context.data_pointer),
+        match loaded.instruction {
+            Instruction::IncrementDataPointer | Instruction::DecrementDataPointer => {
+                let origin = context.origin;
+                let amount = if loaded.instruction == Instruction::IncrementDataPointer {
+                    1
+                } else {
+                    -1
+                };
+                context
+                    .move_data_pointer(amount)
+                    .map_err(|e| error(e.into()))?;
+                // Cells added before the start of the tape moved the old cell to the right.
+                let old = pointer + (context.origin - origin);
+                let new = context.data_pointer;
+                context.notify(|observer, _| observer.pointer_moved(old, new));
+            }
+            Instruction::IncrementData => context
+                .increment_cell(pointer)
+                .map_err(|_| error(RuntimeErrorKind::overflow(1)))?,
+            Instruction::DecrementData => context
+                .decrement_cell(pointer)
+                .map_err(|_| error(RuntimeErrorKind::overflow(-1)))?,
             Instruction::OutputData => {
-                context.write(context.read_cell(context.data_pointer));
+                let value = context.read_cell(pointer);
+                context.write(value.clone()).map_err(|e| error(e.into()))?;
+                context.notify(|observer, _| observer.output_written(&value));
             }
             Instruction::AcceptData => {
-                *context.get_cell(context.data_pointer) = context.read();
+                let read = context.read_to_cell(pointer).map_err(|e| error(e.into()))?;
+                if context.has_observers() {
+                    let value = read.then(|| context.read_cell(pointer));
+                    context.notify(|observer, _| observer.input_read(value.as_ref()));
+                }
             }
             Instruction::JumpForwardsIfZero => {
-                if context.read_cell(context.data_pointer) == T::zero() {
-                    self.jump_forwards(context);
+                if context.cell_is_zero(pointer) {
+                    self.jump_forwards();
                     next_instr = false;
+                } else {
+                    let start = self.instruction_pointer;
+                    context.notify(|observer, _| observer.loop_entered(start));
                 }
             }
             Instruction::JumpBackwardsIfNonzero => {
-                if context.read_cell(context.data_pointer) != T::zero() {
-                    self.jump_backwards(context);
+                let start = loaded.partner.unwrap_or_default();
+                if !context.cell_is_zero(pointer) {
+                    self.jump_backwards();
                     next_instr = false;
+                    context.notify(|observer, _| observer.loop_repeated(start));
+                } else {
+                    context.notify(|observer, _| observer.loop_exited(start));
                 }
             }
         }
+        if let Some(old) = old_cell {
+            let new = context.read_cell(pointer);
+            if old != new {
+                context.notify(|observer, _| observer.cell_changed(pointer, &old, &new));
+            }
+        }
         if next_instr {
             self.instruction_pointer += 1;
         }
-        context.refresh(self);
         self.cycles += 1;
+        context.notify(|observer, context| observer.instruction_executed(self, context, loaded));
+        if self.has_remaining_instructions() {
+            Ok(StepOutcome::Continue)
+        } else {
+            Ok(StepOutcome::Finished)
+        }
+    }
+
+    /// Executes instructions until the script finishes or fails.
+    pub fn run<T: CellType>(
+        &mut self,
+        context: &mut RuntimeContext<T>,
+    ) -> Result<StepOutcome, RuntimeError> {
+        loop {
+            let outcome = self.execute_instruction(context)?;
+            if outcome != StepOutcome::Continue {
+                return Ok(outcome);
+            }
+        }
     }
     pub fn instruction(&self) -> Option<Instruction> {
         self.instructions
@@ -150,7 +495,100 @@ impl Script {
         self.instructions.get(self.instruction_pointer).cloned()
     }
 
+    /// The 1-based line and column (in characters) of a source position.
+    pub fn line_column(&self, source_position: usize) -> (usize, usize) {
+        let (mut line, mut column) = (1, 1);
+        for ch in self.source.chars().take(source_position) {
+            if ch == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+        (line, column)
+    }
+
     pub fn has_remaining_instructions(&self) -> bool {
         self.instructions.len() > self.instruction_pointer
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unmatched(
+        instruction: Instruction,
+        position: usize,
+        line: usize,
+        column: usize,
+    ) -> UnmatchedBracket {
+        UnmatchedBracket {
+            instruction,
+            source_position: position,
+            line,
+            column,
+        }
+    }
+
+    #[test]
+    fn unclosed_bracket() {
+        let error = Script::new("[[]".to_owned())
+            .err()
+            .expect("the script doesn't load");
+        assert_eq!(
+            error.unmatched,
+            [unmatched(Instruction::JumpForwardsIfZero, 0, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn every_unmatched_bracket_in_order() {
+        let error = Script::new("+\n]][".to_owned())
+            .err()
+            .expect("the script doesn't load");
+        assert_eq!(
+            error.unmatched,
+            [
+                unmatched(Instruction::JumpBackwardsIfNonzero, 2, 2, 1),
+                unmatched(Instruction::JumpBackwardsIfNonzero, 3, 2, 2),
+                unmatched(Instruction::JumpForwardsIfZero, 4, 2, 3),
+            ]
+        );
+        assert_eq!(
+            error.to_string(),
+            "unmatched ']' at line 2, column 1 (position 2); \
+             unmatched ']' at line 2, column 2 (position 3); \
+             unmatched '[' at line 2, column 3 (position 4)"
+        );
+    }
+
+    #[test]
+    fn partners_are_symmetric() {
+        let script = Script::new("+[>[-]<[->+<]]".to_owned()).expect("the script loads");
+        for (i, loaded) in script.instructions.iter().enumerate() {
+            let is_bracket = matches!(
+                loaded.instruction,
+                Instruction::JumpForwardsIfZero | Instruction::JumpBackwardsIfNonzero
+            );
+            assert_eq!(loaded.partner.is_some(), is_bracket, "instruction {}", i);
+            if let Some(partner) = loaded.partner {
+                assert_eq!(script.instructions[partner].partner, Some(i));
+                assert_ne!(script.instructions[partner].instruction, loaded.instruction);
+            }
+        }
+        assert_eq!(script.instructions[1].partner, Some(13));
+        assert_eq!(script.instructions[3].partner, Some(5));
+    }
+
+    #[test]
+    fn line_column_counts_characters() {
+        let script = Script::new("+\nä+\n\n>".to_owned()).expect("the script loads");
+        let positions = script.instructions.iter().map(|v| v.source_position);
+        let positions: Vec<_> = positions.map(|p| script.line_column(p)).collect();
+        assert_eq!(positions, [(1, 1), (2, 2), (4, 1)]);
+        assert_eq!(script.line_column(0), (1, 1));
+        assert_eq!(script.line_column(2), (2, 1));
+    }
+}
@@ -0,0 +1,258 @@
+use crate::{CellType, Instruction, LoadedInstruction, RuntimeContext, Script};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// A place in the script to stop at.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Breakpoint {
+    /// Before the instruction with this index in the script.
+    Instruction(usize),
+    /// Before the instruction at this (character) position in the source.
+    SourcePosition(usize),
+    /// Before every instruction that follows a `#` in the source.
+    Hash,
+}
+impl Display for Breakpoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Breakpoint::Instruction(index) => write!(f, "instruction {}", index),
+            Breakpoint::SourcePosition(position) => write!(f, "position {}", position),
+            Breakpoint::Hash => f.write_str("'#'"),
+        }
+    }
+}
+/// Parses `#`, `@<position>` or `<instruction index>`.
+impl FromStr for Breakpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid breakpoint '{}' (expected #, @position or index)",
+                s
+            )
+        };
+        if s == "#" {
+            Ok(Breakpoint::Hash)
+        } else if let Some(position) = s.strip_prefix('@') {
+            position
+                .parse()
+                .map(Breakpoint::SourcePosition)
+                .map_err(|_| invalid())
+        } else {
+            s.parse()
+                .map(Breakpoint::Instruction)
+                .map_err(|_| invalid())
+        }
+    }
+}
+
+/// What happening to a cell to stop at.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Watch<T> {
+    /// Before an instruction reads the cell (`.`, `[` or `]`).
+    Read,
+    /// Before an instruction writes to the cell (`+`, `-` or `,`).
+    Write,
+    /// After an instruction writes this value to the cell.
+    Value(T),
+}
+
+/// A cell to stop at when something happens to it.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Watchpoint<T> {
+    /// Index into [`RuntimeContext::data`] of the cell.
+    pub cell: usize,
+    pub watch: Watch<T>,
+}
+impl<T: CellType> Display for Watchpoint<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.watch {
+            Watch::Read => write!(f, "reads of cell {}", self.cell),
+            Watch::Write => write!(f, "writes to cell {}", self.cell),
+            Watch::Value(value) => write!(f, "cell {} = {}", self.cell, value),
+        }
+    }
+}
+/// Parses `<cell>:read`, `<cell>:write` or `<cell>=<value>`. A cell on its own watches writes.
+impl<T: CellType> FromStr for Watchpoint<T> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid watchpoint '{}' (expected cell:read, cell:write or cell=value)",
+                s
+            )
+        };
+        let (cell, watch) = if let Some((cell, value)) = s.split_once('=') {
+            let value = T::from_str_radix(value.trim(), 10).map_err(|_| invalid())?;
+            (cell, Watch::Value(value))
+        } else {
+            match s.split_once(':') {
+                Some((cell, "read")) => (cell, Watch::Read),
+                Some((cell, "write")) => (cell, Watch::Write),
+                Some(_) => return Err(invalid()),
+                None => (s, Watch::Write),
+            }
+        };
+        let cell = cell.trim().parse().map_err(|_| invalid())?;
+        Ok(Self { cell, watch })
+    }
+}
+
+/// Why a script stopped at a breakpoint or watchpoint.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum BreakReason<T> {
+    Breakpoint(Breakpoint),
+    Watchpoint(Watchpoint<T>),
+}
+impl<T: CellType> Display for BreakReason<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BreakReason::Breakpoint(breakpoint) => write!(f, "breakpoint on {}", breakpoint),
+            BreakReason::Watchpoint(watchpoint) => write!(f, "watchpoint on {}", watchpoint),
+        }
+    }
+}
+
+/// The breakpoints and watchpoints of a debugger running scripts with the step engine.
+#[derive(Clone, Debug)]
+pub struct Breakpoints<T> {
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint<T>>,
+}
+impl<T> Default for Breakpoints<T> {
+    fn default() -> Self {
+        Self {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+        }
+    }
+}
+impl<T: CellType> Breakpoints<T> {
+    pub fn is_empty(&self) -> bool {
+        self.breakpoints.is_empty() && self.watchpoints.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+    }
+
+    /// Checks whether the script should stop before running its next instruction.
+    /// `executed` is the instruction that just ran, if any, for watching the values it wrote.
+    pub fn check(
+        &self,
+        script: &Script,
+        context: &RuntimeContext<T>,
+        executed: Option<LoadedInstruction>,
+    ) -> Option<BreakReason<T>> {
+        let pointer = context.data_pointer;
+        let wrote = executed.is_some_and(|executed| writes_cell(executed.instruction));
+        for watchpoint in &self.watchpoints {
+            if let Watch::Value(value) = &watchpoint.watch {
+                if wrote && watchpoint.cell == pointer && context.read_cell(pointer) == *value {
+                    return Some(BreakReason::Watchpoint(watchpoint.clone()));
+                }
+            }
+        }
+
+        let next = script.loaded_instruction()?;
+        for &breakpoint in &self.breakpoints {
+            let hit = match breakpoint {
+                Breakpoint::Instruction(index) => index == script.instruction_pointer,
+                Breakpoint::SourcePosition(position) => position == next.source_position,
+                Breakpoint::Hash => next.follows_hash,
+            };
+            if hit {
+                return Some(BreakReason::Breakpoint(breakpoint));
+            }
+        }
+        for watchpoint in &self.watchpoints {
+            let hit = watchpoint.cell == pointer
+                && match watchpoint.watch {
+                    Watch::Read => reads_cell(next.instruction),
+                    Watch::Write => writes_cell(next.instruction),
+                    Watch::Value(_) => false,
+                };
+            if hit {
+                return Some(BreakReason::Watchpoint(watchpoint.clone()));
+            }
+        }
+        None
+    }
+}
+
+fn reads_cell(instruction: Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::OutputData
+            | Instruction::JumpForwardsIfZero
+            | Instruction::JumpBackwardsIfNonzero
+    )
+}
+fn writes_cell(instruction: Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::IncrementData | Instruction::DecrementData | Instruction::AcceptData
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn breakpoints_parse() {
+        assert_eq!("12".parse(), Ok(Breakpoint::Instruction(12)));
+        assert_eq!("@7".parse(), Ok(Breakpoint::SourcePosition(7)));
+        assert_eq!("#".parse(), Ok(Breakpoint::Hash));
+        for invalid in ["", "@", "@x", "-1", "##"] {
+            assert!(invalid.parse::<Breakpoint>().is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn watchpoints_parse() {
+        let watchpoint = |s: &str| s.parse::<Watchpoint<u8>>();
+        let expected = |cell, watch| Ok(Watchpoint { cell, watch });
+        assert_eq!(watchpoint("3:read"), expected(3, Watch::Read));
+        assert_eq!(watchpoint("3:write"), expected(3, Watch::Write));
+        assert_eq!(watchpoint("3"), expected(3, Watch::Write));
+        assert_eq!(watchpoint("3 = 65"), expected(3, Watch::Value(65)));
+        for invalid in ["", "3:peek", "x:read", "3=", "3=256", "=1"] {
+            assert!(watchpoint(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn breakpoints_stop_the_script() {
+        let mut script = Script::new("+#>.<,".to_owned()).expect("script loads");
+        let mut context = RuntimeContext::<u8>::new(|| Ok(Some(9)), |_| Ok(()));
+        let mut breakpoints = Breakpoints::default();
+        breakpoints.breakpoints.push(Breakpoint::Hash);
+        breakpoints.watchpoints.push("1:read".parse().unwrap());
+        breakpoints.watchpoints.push("0=9".parse().unwrap());
+
+        let mut stops = Vec::new();
+        let mut executed = None;
+        while script.has_remaining_instructions() {
+            if let Some(reason) = breakpoints.check(&script, &context, executed) {
+                stops.push((script.instruction_pointer, reason));
+            }
+            executed = script.loaded_instruction();
+            script.execute_instruction(&mut context).unwrap();
+        }
+        if let Some(reason) = breakpoints.check(&script, &context, executed) {
+            stops.push((script.instruction_pointer, reason));
+        }
+        assert_eq!(
+            stops,
+            [
+                (1, BreakReason::Breakpoint(Breakpoint::Hash)),
+                (2, BreakReason::Watchpoint("1:read".parse().unwrap())),
+                (5, BreakReason::Watchpoint("0=9".parse().unwrap())),
+            ]
+        );
+    }
+}
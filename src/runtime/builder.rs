@@ -0,0 +1,160 @@
+use crate::{CellType, EofBehaviour, ExecutionObserver, OverflowMode, RuntimeContext, TapePolicy};
+use std::io::{self, stdin, stdout, ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+
+type ReadFn<T> = Box<dyn FnMut() -> io::Result<Option<T>>>;
+type WriteFn<T> = Box<dyn FnMut(T) -> io::Result<()>>;
+
+/// Builds a [`RuntimeContext`]. By default it reads no input, discards its output and has no limits.
+pub struct RuntimeContextBuilder<T: CellType> {
+    read: ReadFn<T>,
+    write: WriteFn<T>,
+    tape_policy: TapePolicy,
+    max_tape_length: Option<usize>,
+    max_cycles: Option<usize>,
+    timeout: Option<Duration>,
+    max_output: Option<usize>,
+    min_cell_value: T,
+    max_cell_value: T,
+    overflow_mode: OverflowMode,
+    eof_behaviour: EofBehaviour,
+    observers: Vec<Box<dyn ExecutionObserver<T>>>,
+}
+
+impl<T: CellType> Default for RuntimeContextBuilder<T> {
+    fn default() -> Self {
+        Self {
+            read: Box::new(|| Ok(None)),
+            write: Box::new(|_| Ok(())),
+            tape_policy: TapePolicy::default(),
+            max_tape_length: None,
+            max_cycles: None,
+            timeout: None,
+            max_output: None,
+            min_cell_value: T::min_value(),
+            max_cell_value: T::max_value(),
+            overflow_mode: OverflowMode::default(),
+            eof_behaviour: EofBehaviour::default(),
+            observers: Vec::new(),
+        }
+    }
+}
+
+impl<T: CellType> RuntimeContextBuilder<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads input one byte at a time from `reader`.
+    pub fn input(self, mut reader: impl Read + 'static) -> Self {
+        self.read_with(move || read_byte(&mut reader).map(|byte| byte.map(T::from_u8)))
+    }
+    /// Writes the big-endian bytes of every output cell to `writer`.
+    pub fn output(self, mut writer: impl Write + 'static) -> Self {
+        self.write_with(move |value| writer.write_all(&value.as_u8_array()))
+    }
+    /// Reads input from stdin and writes output to stdout, flushing it before every read.
+    pub fn stdio(self) -> Self {
+        self.read_with(|| {
+            stdout().flush()?;
+            Ok(read_byte(&mut stdin())?.map(T::from_u8))
+        })
+        .output(stdout())
+    }
+    /// Reads input by calling `read`, which returns `None` at the end of the input.
+    pub fn read_with(mut self, read: impl FnMut() -> io::Result<Option<T>> + 'static) -> Self {
+        self.read = Box::new(read);
+        self
+    }
+    /// Writes output by calling `write` with every output cell.
+    pub fn write_with(mut self, write: impl FnMut(T) -> io::Result<()> + 'static) -> Self {
+        self.write = Box::new(write);
+        self
+    }
+
+    pub fn tape_policy(mut self, tape_policy: TapePolicy) -> Self {
+        self.tape_policy = tape_policy;
+        self
+    }
+    /// Maximum number of cells on the tape, or the length of a circular tape.
+    pub fn max_tape_length(mut self, max_tape_length: Option<usize>) -> Self {
+        self.max_tape_length = max_tape_length;
+        self
+    }
+    /// Maximum number of cycles to run for. See [`RuntimeContext::max_cycles`] for what counts
+    /// as a cycle.
+    pub fn max_cycles(mut self, max_cycles: Option<usize>) -> Self {
+        self.max_cycles = max_cycles;
+        self
+    }
+    /// Maximum time to run for, counted from when the context is built.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    /// Maximum number of bytes to write to the output.
+    pub fn max_output(mut self, max_output: Option<usize>) -> Self {
+        self.max_output = max_output;
+        self
+    }
+    /// Limits cell values to `min..=max` instead of the bounds of the cell type.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min` is greater than `max`.
+    pub fn cell_bounds(mut self, min: T, max: T) -> Self {
+        assert!(min <= max, "the minimum cell value is above the maximum");
+        self.min_cell_value = min;
+        self.max_cell_value = max;
+        self
+    }
+    pub fn overflow_mode(mut self, overflow_mode: OverflowMode) -> Self {
+        self.overflow_mode = overflow_mode;
+        self
+    }
+    pub fn eof_behaviour(mut self, eof_behaviour: EofBehaviour) -> Self {
+        self.eof_behaviour = eof_behaviour;
+        self
+    }
+
+    /// Adds an observer for the events of the step engine.
+    pub fn observer(mut self, observer: impl ExecutionObserver<T> + 'static) -> Self {
+        self.observers.push(Box::new(observer));
+        self
+    }
+
+    pub fn build(self) -> RuntimeContext<T> {
+        RuntimeContext {
+            data: Vec::with_capacity(30000), // Minimum capacity according to Wikipedia
+            data_pointer: 0,
+            origin: 0,
+            tape_policy: self.tape_policy,
+            max_tape_length: self.max_tape_length,
+            max_cycles: self.max_cycles,
+            timeout: self.timeout,
+            started: Instant::now(),
+            max_output: self.max_output,
+            output_len: 0,
+            min_cell_value: self.min_cell_value,
+            max_cell_value: self.max_cell_value,
+            overflow_mode: self.overflow_mode,
+            eof_behaviour: self.eof_behaviour,
+            observers: self.observers,
+            read_fn: self.read,
+            write_fn: self.write,
+        }
+    }
+}
+
+/// Reads a single byte, or returns `None` at the end of the input.
+fn read_byte(reader: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0u8];
+    loop {
+        match reader.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! A compact bytecode compiled from the [IR](crate::ir), and run by the same interpreter.
+use crate::ir::{self, Op};
+use crate::{CellType, LoadedInstruction, RuntimeContext, RuntimeError};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Opcode {
+    Add(i32),
+    Move(i32),
+    Output,
+    Input,
+    /// Jumps are relative to the jumping opcode.
+    JumpIfZero(i32),
+    JumpIfNonzero(i32),
+    Clear,
+    MulAdd {
+        offset: i32,
+        factor: i32,
+    },
+    Scan(i32),
+}
+
+#[derive(Debug)]
+pub struct OperandTooLarge {
+    pub source_position: usize,
+}
+impl Display for OperandTooLarge {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "operand of the operation at position {} does not fit in bytecode",
+            self.source_position
+        )
+    }
+}
+impl Error for OperandTooLarge {}
+
+pub struct Bytecode {
+    pub code: Vec<Opcode>,
+    /// Index into `source` for each opcode in `code`.
+    pub source_indices: Vec<usize>,
+    /// The instructions of the script the bytecode was compiled from, for reporting errors.
+    pub source: Vec<LoadedInstruction>,
+}
+
+impl Bytecode {
+    pub fn compile(program: &ir::Program) -> Result<Self, OperandTooLarge> {
+        let mut code = Vec::with_capacity(program.instructions.len());
+        let mut source_indices = Vec::with_capacity(program.instructions.len());
+        // Where each IR instruction ended up, so jump targets can be translated.
+        let mut addresses = Vec::with_capacity(program.instructions.len());
+        for instruction in &program.instructions {
+            addresses.push(code.len());
+            let position = program.source[instruction.source_index].source_position;
+            let mut emit = |opcode| {
+                code.push(opcode);
+                source_indices.push(instruction.source_index);
+            };
+            match instruction.op {
+                Op::Add(amount) => split_operand(amount, |v| emit(Opcode::Add(v))),
+                Op::Move(amount) => split_operand(amount, |v| emit(Opcode::Move(v))),
+                Op::Output => emit(Opcode::Output),
+                Op::Input => emit(Opcode::Input),
+                // Targets are patched once every address is known.
+                Op::JumpIfZero(_) => emit(Opcode::JumpIfZero(0)),
+                Op::JumpIfNonzero(_) => emit(Opcode::JumpIfNonzero(0)),
+                Op::Clear => emit(Opcode::Clear),
+                Op::MulAdd { offset, factor } => emit(Opcode::MulAdd {
+                    offset: narrow(offset, position)?,
+                    factor: narrow(factor, position)?,
+                }),
+                Op::Scan(step) => emit(Opcode::Scan(narrow(step, position)?)),
+            }
+        }
+        for (instruction, &address) in program.instructions.iter().zip(&addresses) {
+            match instruction.op {
+                Op::JumpIfZero(target) | Op::JumpIfNonzero(target) => {
+                    let offset = addresses[target] as isize - address as isize;
+                    let position = program.source[instruction.source_index].source_position;
+                    let offset = narrow(offset, position)?;
+                    code[address] = match code[address] {
+                        Opcode::JumpIfZero(_) => Opcode::JumpIfZero(offset),
+                        _ => Opcode::JumpIfNonzero(offset),
+                    };
+                }
+                _ => {}
+            }
+        }
+        Ok(Self {
+            code,
+            source_indices,
+            source: program.source.clone(),
+        })
+    }
+
+    pub fn run<T: CellType>(&self, context: &mut RuntimeContext<T>) -> Result<(), RuntimeError> {
+        ir::run(self, context)
+    }
+}
+
+impl ir::Code for Bytecode {
+    fn len(&self) -> usize {
+        self.code.len()
+    }
+    fn op(&self, index: usize) -> Op {
+        let target = |offset: i32| index.wrapping_add_signed(offset as isize);
+        match self.code[index] {
+            Opcode::Add(amount) => Op::Add(amount as isize),
+            Opcode::Move(amount) => Op::Move(amount as isize),
+            Opcode::Output => Op::Output,
+            Opcode::Input => Op::Input,
+            Opcode::JumpIfZero(offset) => Op::JumpIfZero(target(offset)),
+            Opcode::JumpIfNonzero(offset) => Op::JumpIfNonzero(target(offset)),
+            Opcode::Clear => Op::Clear,
+            Opcode::MulAdd { offset, factor } => Op::MulAdd {
+                offset: offset as isize,
+                factor: factor as isize,
+            },
+            Opcode::Scan(step) => Op::Scan(step as isize),
+        }
+    }
+    fn source(&self, index: usize) -> LoadedInstruction {
+        self.source[self.source_indices[index]]
+    }
+}
+
+/// Splits an amount that does not fit in an operand into several operands.
+fn split_operand(mut amount: isize, mut emit: impl FnMut(i32)) {
+    while amount != 0 {
+        let part = amount.clamp(i32::MIN as isize, i32::MAX as isize);
+        emit(part as i32);
+        amount -= part;
+    }
+}
+
+fn narrow(value: isize, source_position: usize) -> Result<i32, OperandTooLarge> {
+    i32::try_from(value).map_err(|_| OperandTooLarge { source_position })
+}
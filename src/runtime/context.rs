@@ -1,133 +1,358 @@
-use crate::Script;
+use crate::{
+    EofBehaviour, ExecutionObserver, OverflowMode, RuntimeContextBuilder, RuntimeErrorKind,
+    TapePolicy,
+};
+use num_bigint::{BigInt, Sign};
+use num_traits::{Num, One, ToPrimitive, Zero};
 use smallvec::SmallVec;
-use std::io::{stdin, stdout, Read, Write};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io;
 use std::ops::{Add, Sub};
+use std::time::{Duration, Instant};
+
+/// Length of circular tapes that don't have a maximum length set.
+pub const DEFAULT_CIRCULAR_TAPE_LENGTH: usize = 30000;
+
+/// Why the data pointer could not be moved.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum TapeError {
+    /// Moved left of the first cell of a left-bounded tape.
+    Underflow,
+    /// The tape would have to hold more than `limit` cells.
+    LimitExceeded { limit: usize },
+}
+impl Display for TapeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TapeError::Underflow => f.write_str("data pointer moved past the start of the tape"),
+            TapeError::LimitExceeded { limit } => {
+                write!(f, "tape grew past its maximum length of {} cells", limit)
+            }
+        }
+    }
+}
+impl Error for TapeError {}
+
+/// A cell went past its bounds while the overflow mode is [`OverflowMode::Trap`].
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct CellOverflow;
+
+/// Why a value of input could not be read into a cell.
+#[derive(Debug)]
+pub enum InputError {
+    /// Input was read after its end while the EOF behaviour is [`EofBehaviour::Error`].
+    EndOfInput,
+    Io(io::Error),
+}
+impl From<io::Error> for InputError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+/// Why a value could not be written to the output.
+#[derive(Debug)]
+pub enum OutputError {
+    /// Writing the value would take the output past `limit` bytes.
+    LimitExceeded {
+        limit: usize,
+    },
+    Io(io::Error),
+}
+impl From<io::Error> for OutputError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+/// How many cycles run between checks of the time limit, since reading the clock is slow.
+pub const TIME_CHECK_INTERVAL: usize = 1024;
 
 pub struct RuntimeContext<T>
 where
     T: CellType,
 {
     pub data: Vec<T>,
+    /// Index into `data` of the current cell.
     pub data_pointer: usize,
+    /// Index into `data` of the cell the data pointer started at.
+    /// Only bidirectional tapes have cells before it.
+    pub origin: usize,
+    pub tape_policy: TapePolicy,
+    /// Maximum number of cells on the tape, or the length of a circular tape.
+    pub max_tape_length: Option<usize>,
+    /// Maximum number of cycles to run for before stopping with an error.
+    /// The step engine counts every instruction as a cycle, and the optimised engines count
+    /// every operation (which can stand for many instructions) and every step of a scan loop.
+    pub max_cycles: Option<usize>,
+    /// Maximum time to run for, counted from [`started`](Self::started).
+    pub timeout: Option<Duration>,
+    /// When the context was built.
+    pub started: Instant,
+    /// Maximum number of bytes to write to the output.
+    pub max_output: Option<usize>,
+    /// Number of bytes written to the output so far.
+    pub output_len: usize,
 
     pub min_cell_value: T,
     pub max_cell_value: T,
+    pub overflow_mode: OverflowMode,
+    pub eof_behaviour: EofBehaviour,
 
-    pub refresh_fn: Option<Box<dyn Fn(&Script, &Self)>>,
-    pub read_fn: Box<dyn FnMut() -> T>,
-    pub write_fn: Box<dyn FnMut(T)>,
+    /// Observers of the step engine, see [`ExecutionObserver`].
+    pub observers: Vec<Box<dyn ExecutionObserver<T>>>,
+    /// Reads the next value of input, or returns `None` at the end of it.
+    pub read_fn: Box<dyn FnMut() -> io::Result<Option<T>>>,
+    pub write_fn: Box<dyn FnMut(T) -> io::Result<()>>,
 }
 
 impl<T> RuntimeContext<T>
 where
     T: CellType,
 {
-    pub fn new(read: impl FnMut() -> T + 'static, write: impl FnMut(T) + 'static) -> Self {
-        Self {
-            data: Vec::with_capacity(30000), // Minimum capacity according to Wikipedia
-            data_pointer: 0,
-            min_cell_value: T::min_value(),
-            max_cell_value: T::max_value(),
-            refresh_fn: None,
-            read_fn: Box::new(read),
-            write_fn: Box::new(write),
-        }
+    pub fn new(
+        read: impl FnMut() -> io::Result<Option<T>> + 'static,
+        write: impl FnMut(T) -> io::Result<()> + 'static,
+    ) -> Self {
+        Self::builder().read_with(read).write_with(write).build()
     }
     pub fn new_stdio() -> Self {
-        Self::new(
-            || {
-                let mut value = [0u8];
-                stdin().read_exact(&mut value).expect("Could not read");
-                T::from_u8(value[0])
-            },
-            |value| {
-                stdout()
-                    .write(&value.as_u8_array())
-                    .expect("Could not write");
-            },
-        )
+        Self::builder().stdio().build()
+    }
+    pub fn builder() -> RuntimeContextBuilder<T> {
+        RuntimeContextBuilder::new()
     }
 
     pub fn get_cell(&mut self, i: usize) -> &mut T {
         if self.data.len() <= i {
             self.data.resize(i + 1, T::zero());
         }
-        let ptr = &mut self.data[i];
-
-        ptr
+        &mut self.data[i]
     }
+    /// Wraps a cell that's outside of the cell bounds back into them.
     pub fn fix_cell(&mut self, i: usize) {
-        let max = self.max_cell_value;
-        let min = self.min_cell_value;
+        let max = self.max_cell_value.clone();
+        let min = self.min_cell_value.clone();
         let cell = self.get_cell(i);
-        let diff = max - min;
-        while *cell > max {
-            *cell = *cell - diff;
-        }
-        while *cell < min {
-            *cell = *cell + diff;
-        }
+        *cell = cell.wrapping_offset(0, min, max);
     }
     pub fn read_cell(&self, i: usize) -> T {
         if self.data.len() <= i {
             return T::zero();
         }
-        self.data[i]
+        self.data[i].clone()
+    }
+    /// Whether a cell is zero, without copying it out like [`read_cell`](Self::read_cell).
+    pub fn cell_is_zero(&self, i: usize) -> bool {
+        self.data.get(i).is_none_or(CellType::is_zero)
+    }
+    pub fn increment_cell(&mut self, i: usize) -> Result<(), CellOverflow> {
+        self.add_to_cell(i, 1)
     }
-    pub fn increment_cell(&mut self, i: usize) {
-        let max = self.max_cell_value;
-        let min = self.min_cell_value;
+    /// Adds `amount` to a cell, handling the cell bounds as the overflow mode says.
+    /// A cell that traps is left at the bound it reached, as if `amount` was added one at a time.
+    pub fn add_to_cell(&mut self, i: usize, amount: i128) -> Result<(), CellOverflow> {
+        let max = self.max_cell_value.clone();
+        let min = self.min_cell_value.clone();
+        let mode = self.overflow_mode;
         let cell = self.get_cell(i);
-        if *cell >= max {
-            *cell = min;
-        } else {
-            *cell = *cell + T::one();
+        *cell = match mode {
+            OverflowMode::Wrap => cell.wrapping_offset(amount, min, max),
+            OverflowMode::Saturate => cell.saturating_offset(amount, min, max),
+            OverflowMode::Trap => match cell.checked_offset(amount, min.clone(), max.clone()) {
+                Some(value) => value,
+                None => {
+                    *cell = cell.saturating_offset(amount, min, max);
+                    return Err(CellOverflow);
+                }
+            },
+        };
+        Ok(())
+    }
+    pub fn decrement_cell(&mut self, i: usize) -> Result<(), CellOverflow> {
+        self.add_to_cell(i, -1)
+    }
+
+    /// Index of the cell `offset` cells away from the data pointer, following the tape policy.
+    /// On bidirectional tapes, cells added to the start of `data` move the pointer and origin too.
+    pub fn relative_cell(&mut self, offset: isize) -> Result<usize, TapeError> {
+        let pointer = self.data_pointer as isize;
+        match self.tape_policy {
+            TapePolicy::LeftBounded => {
+                let target = self
+                    .data_pointer
+                    .checked_add_signed(offset)
+                    .ok_or(TapeError::Underflow)?;
+                match self.max_tape_length {
+                    Some(limit) if target >= limit => Err(TapeError::LimitExceeded { limit }),
+                    _ => Ok(target),
+                }
+            }
+            TapePolicy::Bidirectional => {
+                let target = pointer + offset;
+                // Cells from the first one in `data` (or the target, if it's before that)
+                // up to the last one in `data` (or the target, if it's after that).
+                let len = (self.data.len() as isize).max(target + 1) - target.min(0);
+                if let Some(limit) = self.max_tape_length {
+                    if len as usize > limit {
+                        return Err(TapeError::LimitExceeded { limit });
+                    }
+                }
+                if target >= 0 {
+                    return Ok(target as usize);
+                }
+                let added = target.unsigned_abs();
+                self.data
+                    .splice(0..0, std::iter::repeat_n(T::zero(), added));
+                self.data_pointer += added;
+                self.origin += added;
+                Ok(0)
+            }
+            TapePolicy::Circular => {
+                let len = self
+                    .max_tape_length
+                    .unwrap_or(DEFAULT_CIRCULAR_TAPE_LENGTH)
+                    .max(1) as isize;
+                Ok((pointer + offset % len).rem_euclid(len) as usize)
+            }
         }
     }
-    pub fn decrement_cell(&mut self, i: usize) {
-        let max = self.max_cell_value;
-        let min = self.min_cell_value;
-        let cell = self.get_cell(i);
-        if *cell <= min {
-            *cell = max;
-        } else {
-            *cell = *cell - T::one();
+    pub fn move_data_pointer(&mut self, amount: isize) -> Result<(), TapeError> {
+        self.data_pointer = self.relative_cell(amount)?;
+        Ok(())
+    }
+
+    /// Removes the zero cells at the end of the tape, keeping the ones before the origin.
+    /// Engines add cells at different times, so their tapes only match once they're trimmed.
+    pub fn trim_tape(&mut self) {
+        while self.data.len() > self.origin && self.data.last().is_some_and(T::is_zero) {
+            self.data.pop();
         }
     }
 
-    pub fn refresh(&self, script: &Script) {
-        if let Some(refresh_fn) = self.refresh_fn.as_ref() {
-            refresh_fn(script, self);
+    pub fn add_observer(&mut self, observer: impl ExecutionObserver<T> + 'static) {
+        self.observers.push(Box::new(observer));
+    }
+    pub fn has_observers(&self) -> bool {
+        !self.observers.is_empty()
+    }
+    /// Sends an event to every observer, along with the context.
+    pub fn notify(&mut self, mut event: impl FnMut(&mut dyn ExecutionObserver<T>, &Self)) {
+        if self.observers.is_empty() {
+            return;
+        }
+        // The observers are taken out, so that they can look at the rest of the context.
+        let mut observers = std::mem::take(&mut self.observers);
+        for observer in &mut observers {
+            event(observer.as_mut(), self);
         }
+        self.observers = observers;
     }
-    pub fn read(&mut self) -> T {
+    pub fn read(&mut self) -> io::Result<Option<T>> {
         (self.read_fn)()
     }
-    pub fn write(&mut self, value: T) {
-        (self.write_fn)(value)
+    /// Reads the next value of input into a cell, following the EOF behaviour at the end of it.
+    /// Returns whether there was a value to read.
+    pub fn read_to_cell(&mut self, i: usize) -> Result<bool, InputError> {
+        if let Some(value) = self.read()? {
+            *self.get_cell(i) = value;
+            return Ok(true);
+        }
+        let value = match self.eof_behaviour {
+            EofBehaviour::Zero => T::zero(),
+            EofBehaviour::MinusOne => T::zero().wrapping_offset(
+                -1,
+                self.min_cell_value.clone(),
+                self.max_cell_value.clone(),
+            ),
+            EofBehaviour::Unchanged => return Ok(false),
+            EofBehaviour::Error => return Err(InputError::EndOfInput),
+        };
+        *self.get_cell(i) = value;
+        Ok(false)
+    }
+    /// Writes a value to the output, unless it would take the output past its maximum size.
+    pub fn write(&mut self, value: T) -> Result<(), OutputError> {
+        let len = value.as_u8_array().len();
+        if let Some(limit) = self.max_output {
+            if self.output_len + len > limit {
+                return Err(OutputError::LimitExceeded { limit });
+            }
+        }
+        self.output_len += len;
+        (self.write_fn)(value)?;
+        Ok(())
+    }
+
+    /// Whether a cycle or time limit is set, so engines know to call
+    /// [`check_limits`](Self::check_limits).
+    pub fn has_run_limits(&self) -> bool {
+        self.max_cycles.is_some() || self.timeout.is_some()
+    }
+    /// Checks the cycle and time limits after `cycles` cycles.
+    /// The time limit is only checked every [`TIME_CHECK_INTERVAL`] cycles.
+    pub fn check_limits(&self, cycles: usize) -> Result<(), RuntimeErrorKind> {
+        if let Some(limit) = self.max_cycles {
+            if cycles >= limit {
+                return Err(RuntimeErrorKind::CycleLimit { limit });
+            }
+        }
+        if let Some(limit) = self.timeout {
+            if cycles.is_multiple_of(TIME_CHECK_INTERVAL) && self.started.elapsed() > limit {
+                return Err(RuntimeErrorKind::TimeLimit { limit });
+            }
+        }
+        Ok(())
     }
 }
 
 pub type RuntimeContextU8 = RuntimeContext<u8>;
 pub type RuntimeContextU64 = RuntimeContext<u64>;
+pub type RuntimeContextBigInt = RuntimeContext<BigInt>;
 
+/// A type that can be used for cells.
+///
+/// Fixed-width types are bounded by [`min_value`](Self::min_value) and
+/// [`max_value`](Self::max_value), or narrower bounds set on the context.
+/// Unbounded types (where [`BITS`](Self::BITS) is `None`) have neither: both return zero,
+/// and the bounds passed to the offset functions are ignored.
 pub trait CellType:
-    Copy + Clone + Ord + Eq + Sub<Output = Self> + Add<Output = Self> + 'static
+    Clone + Ord + Eq + Display + Sub<Output = Self> + Add<Output = Self> + 'static
 {
+    /// Width of the type in bits, or `None` if it is unbounded.
+    const BITS: Option<u32>;
+
     fn min_value() -> Self;
     fn zero() -> Self;
     fn one() -> Self;
     fn max_value() -> Self;
 
+    /// Converts a byte of input. Signed cells of one byte read it as two's complement,
+    /// so bytes above 127 become negative; wider cells keep the value of the byte.
     fn from_u8(value: u8) -> Self;
+    /// Parses a value, which may start with `-` for signed cells.
+    #[allow(clippy::result_unit_err)]
     fn from_str_radix(str: &str, radix: u32) -> Result<Self, ()>;
 
+    /// The big-endian bytes of the value, in two's complement for signed cells.
     fn as_u8_array(&self) -> SmallVec<[u8; 8]>;
+
+    fn is_zero(&self) -> bool;
+    fn to_i128(&self) -> i128;
+    /// Adds `amount`, wrapping around within `min..=max`.
+    fn wrapping_offset(&self, amount: i128, min: Self, max: Self) -> Self;
+    /// Adds `amount`, stopping at `min` or `max`.
+    fn saturating_offset(&self, amount: i128, min: Self, max: Self) -> Self;
+    /// Adds `amount`, or returns `None` if the result would be outside of `min` and `max`.
+    fn checked_offset(&self, amount: i128, min: Self, max: Self) -> Option<Self>;
 }
 
 macro_rules! cell_type_impl {
     ($ty:ty) => {
         impl CellType for $ty {
+            const BITS: Option<u32> = Some(<$ty>::BITS);
+
             fn min_value() -> Self {
                 Self::MIN
             }
@@ -154,6 +379,28 @@ macro_rules! cell_type_impl {
                 }
                 vec
             }
+
+            fn is_zero(&self) -> bool {
+                *self == 0
+            }
+            fn to_i128(&self) -> i128 {
+                *self as i128
+            }
+            fn wrapping_offset(&self, amount: i128, min: Self, max: Self) -> Self {
+                let (min, max) = (min as i128, max as i128);
+                let range = max - min + 1;
+                ((*self as i128 - min + amount.rem_euclid(range)).rem_euclid(range) + min) as Self
+            }
+            fn saturating_offset(&self, amount: i128, min: Self, max: Self) -> Self {
+                let value = (*self as i128).saturating_add(amount);
+                value.clamp(min as i128, max as i128) as Self
+            }
+            fn checked_offset(&self, amount: i128, min: Self, max: Self) -> Option<Self> {
+                let value = (*self as i128).checked_add(amount)?;
+                (min as i128..=max as i128)
+                    .contains(&value)
+                    .then_some(value as Self)
+            }
         }
     };
 }
@@ -163,3 +410,165 @@ cell_type_impl!(u16);
 cell_type_impl!(u32);
 cell_type_impl!(u64);
 cell_type_impl!(usize);
+cell_type_impl!(i8);
+cell_type_impl!(i16);
+cell_type_impl!(i32);
+cell_type_impl!(i64);
+
+/// Arbitrary-precision cells, which never wrap around.
+/// Output only writes the lowest byte of the value (in two's complement),
+/// since there is no fixed number of bytes to write.
+impl CellType for BigInt {
+    const BITS: Option<u32> = None;
+
+    fn min_value() -> Self {
+        Zero::zero()
+    }
+    fn zero() -> Self {
+        Zero::zero()
+    }
+    fn one() -> Self {
+        One::one()
+    }
+    fn max_value() -> Self {
+        Zero::zero()
+    }
+    fn from_u8(value: u8) -> Self {
+        Self::from(value)
+    }
+    fn from_str_radix(str: &str, radix: u32) -> Result<Self, ()> {
+        <Self as Num>::from_str_radix(str, radix).map_err(|_| ())
+    }
+
+    fn as_u8_array(&self) -> SmallVec<[u8; 8]> {
+        let (sign, digits) = (self % 256u32).to_u32_digits();
+        let low = digits.first().copied().unwrap_or(0) as u8;
+        let byte = if sign == Sign::Minus {
+            low.wrapping_neg()
+        } else {
+            low
+        };
+        SmallVec::from_buf_and_len([byte, 0, 0, 0, 0, 0, 0, 0], 1)
+    }
+
+    fn is_zero(&self) -> bool {
+        Zero::is_zero(self)
+    }
+    fn to_i128(&self) -> i128 {
+        ToPrimitive::to_i128(self).unwrap_or(match self.sign() {
+            Sign::Minus => i128::MIN,
+            _ => i128::MAX,
+        })
+    }
+    fn wrapping_offset(&self, amount: i128, _min: Self, _max: Self) -> Self {
+        self + amount
+    }
+    fn saturating_offset(&self, amount: i128, _min: Self, _max: Self) -> Self {
+        self + amount
+    }
+    fn checked_offset(&self, amount: i128, _min: Self, _max: Self) -> Option<Self> {
+        Some(self + amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(overflow_mode: OverflowMode) -> RuntimeContext<u8> {
+        let mut context = RuntimeContext::new(|| Ok(None), |_| Ok(()));
+        context.overflow_mode = overflow_mode;
+        context
+    }
+
+    #[test]
+    fn add_to_cell_wraps() {
+        let mut context = context(OverflowMode::Wrap);
+        assert_eq!(context.add_to_cell(0, 300), Ok(()));
+        assert_eq!(context.read_cell(0), 44);
+        assert_eq!(context.add_to_cell(0, -45), Ok(()));
+        assert_eq!(context.read_cell(0), 255);
+
+        context.min_cell_value = 10;
+        context.max_cell_value = 19;
+        assert_eq!(context.add_to_cell(1, 25), Ok(()));
+        assert_eq!(context.read_cell(1), 15);
+    }
+
+    #[test]
+    fn add_to_cell_saturates() {
+        let mut context = context(OverflowMode::Saturate);
+        assert_eq!(context.add_to_cell(0, 300), Ok(()));
+        assert_eq!(context.read_cell(0), 255);
+        assert_eq!(context.add_to_cell(0, -1000), Ok(()));
+        assert_eq!(context.read_cell(0), 0);
+    }
+
+    #[test]
+    fn add_to_cell_traps_at_the_bound() {
+        let mut context = context(OverflowMode::Trap);
+        assert_eq!(context.add_to_cell(0, 255), Ok(()));
+        assert_eq!(context.read_cell(0), 255);
+        assert_eq!(context.increment_cell(0), Err(CellOverflow));
+        assert_eq!(context.read_cell(0), 255);
+        assert_eq!(context.add_to_cell(0, -300), Err(CellOverflow));
+        assert_eq!(context.read_cell(0), 0);
+        assert_eq!(context.decrement_cell(0), Err(CellOverflow));
+        assert_eq!(context.read_cell(0), 0);
+    }
+
+    #[test]
+    fn read_to_cell_at_the_end_of_input() {
+        let expected = [
+            (EofBehaviour::Zero, false, 0),
+            (EofBehaviour::MinusOne, false, 255),
+            (EofBehaviour::Unchanged, false, 7),
+            (EofBehaviour::Error, true, 7),
+        ];
+        for (eof_behaviour, is_error, value) in expected {
+            let mut input = [65, 66].into_iter();
+            let mut context = RuntimeContext::<u8>::new(move || Ok(input.next()), |_| Ok(()));
+            context.eof_behaviour = eof_behaviour;
+            assert!(context.read_to_cell(0).is_ok());
+            assert!(context.read_to_cell(1).is_ok());
+            assert_eq!(context.data, [65, 66]);
+            *context.get_cell(2) = 7;
+            match context.read_to_cell(2) {
+                Ok(read) => assert!(!read && !is_error, "{}", eof_behaviour),
+                Err(InputError::EndOfInput) => assert!(is_error, "{}", eof_behaviour),
+                Err(InputError::Io(e)) => panic!("unexpected I/O error: {}", e),
+            }
+            assert_eq!(context.read_cell(2), value, "{}", eof_behaviour);
+        }
+    }
+
+    #[test]
+    fn signed_cells() {
+        assert_eq!(i8::from_u8(200), -56);
+        assert_eq!(i16::from_u8(200), 200);
+        assert_eq!(<i8 as CellType>::from_str_radix("-1f", 16), Ok(-31));
+        assert_eq!((-2i16).as_u8_array().as_slice(), [0xFF, 0xFE]);
+
+        let mut context = RuntimeContext::<i8>::new(|| Ok(None), |_| Ok(()));
+        assert_eq!(context.decrement_cell(0), Ok(()));
+        assert_eq!(context.read_cell(0), -1);
+        assert_eq!(context.add_to_cell(0, -128), Ok(()));
+        assert_eq!(context.read_cell(0), 127);
+    }
+
+    #[test]
+    fn bignum_cells() {
+        let mut context = RuntimeContext::<BigInt>::new(|| Ok(None), |_| Ok(()));
+        context.overflow_mode = OverflowMode::Trap;
+        assert_eq!(context.add_to_cell(0, i128::MAX), Ok(()));
+        assert_eq!(context.add_to_cell(0, i128::MAX), Ok(()));
+        assert_eq!(context.read_cell(0), BigInt::from(i128::MAX) * 2);
+        assert_eq!(CellType::to_i128(&context.read_cell(0)), i128::MAX);
+        assert_eq!(context.decrement_cell(1), Ok(()));
+        assert_eq!(context.read_cell(1), BigInt::from(-1));
+
+        assert_eq!(BigInt::from(258).as_u8_array().as_slice(), [2]);
+        assert_eq!(BigInt::from(-1).as_u8_array().as_slice(), [255]);
+        assert_eq!(BigInt::from(-258).as_u8_array().as_slice(), [254]);
+    }
+}
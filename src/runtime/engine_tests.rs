@@ -0,0 +1,243 @@
+//! Runs the same scripts on every engine and checks that they agree.
+
+use crate::runtime::{
+    bytecode, ir, OverflowMode, RuntimeContext, RuntimeError, RuntimeErrorKind, Script, TapeError,
+    TapePolicy,
+};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+type Engine = fn(&mut Script, &mut RuntimeContext<u8>) -> Result<(), RuntimeError>;
+
+/// What a run leaves behind, as far as engines are expected to agree on it.
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    output: Vec<u8>,
+    /// The nonzero cells and the data pointer, indexed from the origin since engines grow the
+    /// tape at different times.
+    /// Left out after tape errors: optimised engines stop before a move or loop that leaves the
+    /// tape, while the step engine gets part of the way through it.
+    tape: Option<(Vec<(isize, u8)>, isize)>,
+    error: Option<String>,
+}
+
+struct Case {
+    source: &'static str,
+    input: &'static [u8],
+    tape_policy: TapePolicy,
+    max_tape_length: Option<usize>,
+    overflow_mode: OverflowMode,
+    max_cycles: Option<usize>,
+    timeout: Option<Duration>,
+    max_output: Option<usize>,
+}
+
+impl Case {
+    fn new(source: &'static str) -> Self {
+        Self {
+            source,
+            input: b"",
+            tape_policy: TapePolicy::LeftBounded,
+            max_tape_length: None,
+            overflow_mode: OverflowMode::Wrap,
+            max_cycles: None,
+            timeout: None,
+            max_output: None,
+        }
+    }
+    fn input(mut self, input: &'static [u8]) -> Self {
+        self.input = input;
+        self
+    }
+    fn tape(mut self, tape_policy: TapePolicy, max_tape_length: Option<usize>) -> Self {
+        self.tape_policy = tape_policy;
+        self.max_tape_length = max_tape_length;
+        self
+    }
+    fn overflow(mut self, overflow_mode: OverflowMode) -> Self {
+        self.overflow_mode = overflow_mode;
+        self
+    }
+    fn limits(
+        mut self,
+        max_cycles: Option<usize>,
+        timeout: Option<Duration>,
+        max_output: Option<usize>,
+    ) -> Self {
+        self.max_cycles = max_cycles;
+        self.timeout = timeout;
+        self.max_output = max_output;
+        self
+    }
+
+    fn run(
+        &self,
+        engine: impl FnOnce(&mut Script, &mut RuntimeContext<u8>) -> Result<(), RuntimeError>,
+    ) -> Outcome {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let written = output.clone();
+        let mut input = self.input.iter().copied();
+        let mut context = RuntimeContext::<u8>::new(
+            move || Ok(input.next()),
+            move |value| {
+                written.borrow_mut().push(value);
+                Ok(())
+            },
+        );
+        context.tape_policy = self.tape_policy;
+        context.max_tape_length = self.max_tape_length;
+        context.overflow_mode = self.overflow_mode;
+        context.max_cycles = self.max_cycles;
+        context.timeout = self.timeout;
+        context.max_output = self.max_output;
+        let mut script = Script::new(self.source.to_owned()).expect("test scripts parse");
+
+        let result = engine(&mut script, &mut context);
+        let origin = context.origin as isize;
+        let cells = context.data.iter().enumerate();
+        let cells = cells
+            .filter(|(_, value)| **value != 0)
+            .map(|(i, value)| (i as isize - origin, *value))
+            .collect();
+        let tape_error = matches!(&result, Err(e) if matches!(e.kind, RuntimeErrorKind::Tape(_)));
+        let output = output.borrow().clone();
+        Outcome {
+            output,
+            tape: (!tape_error).then(|| (cells, context.data_pointer as isize - origin)),
+            error: result.err().map(|e| e.kind.to_string()),
+        }
+    }
+}
+
+fn cases() -> Vec<Case> {
+    vec![
+        Case::new(include_str!("../../examples/hello-world.bf")),
+        Case::new(include_str!("../../examples/hello-world-compact.bf")),
+        Case::new(include_str!("../../examples/addition.bf")),
+        Case::new(",[.,]").input(b"echo"),
+        Case::new("++++++++[>++++++++<-]>+."),
+        Case::new("++++[>+++<-]>[<++>-]<[>>+>+<<<-]>>>[>]"),
+        Case::new("+>>>+[<]>"),
+        // Cells that are only passed, cleared or changed back and forth.
+        Case::new(">+-"),
+        Case::new(">>>[-]<<<+"),
+        Case::new(">>>"),
+        Case::new("-[+]"),
+        // Leaving the tape.
+        Case::new("<"),
+        Case::new("<>+"),
+        Case::new("+[-<>]"),
+        Case::new("+>+[<]"),
+        Case::new(">>><<<+").tape(TapePolicy::LeftBounded, Some(2)),
+        Case::new("+[>+]").tape(TapePolicy::LeftBounded, Some(8)),
+        Case::new("+[->>+<<]").tape(TapePolicy::LeftBounded, Some(2)),
+        // The other tape policies.
+        Case::new("<<+>>+<[-]<[->>+<<]").tape(TapePolicy::Bidirectional, None),
+        Case::new("<<<+").tape(TapePolicy::Bidirectional, Some(3)),
+        Case::new("+<<[<]").tape(TapePolicy::Bidirectional, Some(4)),
+        Case::new("<+<++>>>+[<]<<").tape(TapePolicy::Circular, Some(4)),
+        Case::new("+>>>>>+<<<<<<[-]").tape(TapePolicy::Circular, Some(5)),
+        Case::new("++[>>>+<<]").tape(TapePolicy::Circular, Some(3)),
+        // The other overflow modes, which leave the cell at the bound it reached on errors.
+        Case::new("+++-----").overflow(OverflowMode::Trap),
+        Case::new(",+++").input(&[254]).overflow(OverflowMode::Trap),
+        Case::new("+[-]-").overflow(OverflowMode::Trap),
+        Case::new("+[->-<]>").overflow(OverflowMode::Trap),
+        Case::new("--+++>,++++")
+            .input(&[253])
+            .overflow(OverflowMode::Saturate),
+        Case::new("+[->+++<]>[-<+>]-").overflow(OverflowMode::Saturate),
+    ]
+}
+
+fn step(script: &mut Script, context: &mut RuntimeContext<u8>) -> Result<(), RuntimeError> {
+    while script.has_remaining_instructions() {
+        script.execute_instruction(context)?;
+    }
+    Ok(())
+}
+
+fn optimise(script: &Script, context: &RuntimeContext<u8>) -> ir::Program {
+    ir::Program::optimise(script, context.overflow_mode, context.tape_policy)
+}
+
+fn bytecode(script: &mut Script, context: &mut RuntimeContext<u8>) -> Result<(), RuntimeError> {
+    bytecode::Bytecode::compile(&optimise(script, context))
+        .expect("test scripts fit in bytecode")
+        .run(context)
+}
+
+#[test]
+fn ir_matches_step() {
+    for case in cases() {
+        let ir = case.run(|script, context| optimise(script, context).run(context));
+        assert_eq!(ir, case.run(step), "script: {:?}", case.source);
+    }
+}
+
+#[test]
+fn bytecode_matches_step() {
+    for case in cases() {
+        assert_eq!(
+            case.run(bytecode),
+            case.run(step),
+            "script: {:?}",
+            case.source
+        );
+    }
+}
+
+/// Runs a case that's expected to fail, returning what it left behind and why it stopped.
+fn stopped(case: Case, engine: Engine) -> (Outcome, RuntimeErrorKind) {
+    let mut kind = None;
+    let outcome = case.run(|script, context| {
+        let result = engine(script, context);
+        kind = result.as_ref().err().map(|e| e.kind.clone());
+        result
+    });
+    (outcome, kind.expect("the script stops with an error"))
+}
+
+#[test]
+fn limits_stop_step_and_bytecode() {
+    for engine in [step as Engine, bytecode] {
+        let (_, kind) = stopped(Case::new("+[]").limits(Some(100), None, None), engine);
+        assert!(matches!(kind, RuntimeErrorKind::CycleLimit { limit: 100 }));
+
+        let timeout = Some(Duration::ZERO);
+        let (_, kind) = stopped(Case::new("+[]").limits(None, timeout, None), engine);
+        assert!(matches!(kind, RuntimeErrorKind::TimeLimit { .. }));
+
+        let (outcome, kind) = stopped(Case::new("+[.]").limits(None, None, Some(5)), engine);
+        assert!(matches!(kind, RuntimeErrorKind::OutputLimit { limit: 5 }));
+        assert_eq!(outcome.output, [1; 5]);
+
+        let tape = Case::new("+[>+]").tape(TapePolicy::LeftBounded, Some(8));
+        let (_, kind) = stopped(tape, engine);
+        assert!(matches!(
+            kind,
+            RuntimeErrorKind::Tape(TapeError::LimitExceeded { limit: 8 })
+        ));
+    }
+}
+
+#[cfg(feature = "jit")]
+#[test]
+fn jit_matches_step() {
+    use crate::runtime::jit::JitProgram;
+
+    for case in cases() {
+        let jit = case.run(|script, context| {
+            let program = optimise(script, context);
+            // Like the binary, fall back to bytecode for tapes the JIT doesn't handle.
+            match JitProgram::compile(&program, context) {
+                Ok(compiled) => compiled.run(context),
+                Err(_) => bytecode::Bytecode::compile(&program)
+                    .expect("test scripts fit in bytecode")
+                    .run(context),
+            }
+        });
+        assert_eq!(jit, case.run(step), "script: {:?}", case.source);
+    }
+}
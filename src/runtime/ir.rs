@@ -0,0 +1,290 @@
+//! Optimised intermediate representation.
+//!
+//! Runs of `+`/`-` and `<`/`>` are folded into counted operations,
+//! and a few common loop shapes are replaced by single operations:
+//! - clear loops (`[-]`, `[+]`),
+//! - move/copy/multiply loops (`[->+>++<<]`),
+//! - scan loops (`[>]`, `[<<]`).
+//!
+//! Loops are only replaced when cells wrap around, and runs that change direction are only folded
+//! when cells wrap (for `+`/`-`) or the tape is circular (for `<`/`>`), so errors happen at the
+//! same point as with the step engine.
+use crate::{
+    CellType, Instruction, LoadedInstruction, OverflowMode, RuntimeContext, RuntimeError,
+    RuntimeErrorKind, Script, TapePolicy,
+};
+use std::collections::BTreeMap;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Op {
+    Add(isize),
+    Move(isize),
+    Output,
+    Input,
+    /// Jumps hold the index of their partner.
+    JumpIfZero(usize),
+    JumpIfNonzero(usize),
+    Clear,
+    /// Adds the current cell times `factor` to the cell at `offset`. Always followed by a `Clear`.
+    MulAdd {
+        offset: isize,
+        factor: isize,
+    },
+    /// Moves the data pointer by this step until it's on a zero cell.
+    Scan(isize),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct OptimisedInstruction {
+    pub op: Op,
+    /// Index into [`Program::source`] of the first instruction this operation was built from.
+    pub source_index: usize,
+}
+
+pub struct Program {
+    pub instructions: Vec<OptimisedInstruction>,
+    /// The instructions of the script the program was built from, for reporting errors.
+    pub source: Vec<LoadedInstruction>,
+}
+
+impl Program {
+    /// Optimises a script for running with the given overflow mode and tape policy.
+    pub fn optimise(script: &Script, overflow_mode: OverflowMode, tape_policy: TapePolicy) -> Self {
+        let wrapping = overflow_mode == OverflowMode::Wrap;
+        let circular = tape_policy == TapePolicy::Circular;
+        let source = &script.instructions;
+        let mut instructions: Vec<OptimisedInstruction> = Vec::new();
+        let mut open_loops = Vec::new();
+        let mut i = 0;
+        while i < source.len() {
+            let loaded = source[i];
+            let at = |op| OptimisedInstruction {
+                op,
+                source_index: i,
+            };
+            match loaded.instruction {
+                Instruction::IncrementData | Instruction::DecrementData => {
+                    let (amount, len) = fold_run(
+                        &source[i..],
+                        Instruction::IncrementData,
+                        Instruction::DecrementData,
+                        wrapping,
+                    );
+                    if amount != 0 {
+                        instructions.push(at(Op::Add(amount)));
+                    }
+                    i += len;
+                    continue;
+                }
+                Instruction::IncrementDataPointer | Instruction::DecrementDataPointer => {
+                    let (amount, len) = fold_run(
+                        &source[i..],
+                        Instruction::IncrementDataPointer,
+                        Instruction::DecrementDataPointer,
+                        circular,
+                    );
+                    if amount != 0 {
+                        instructions.push(at(Op::Move(amount)));
+                    }
+                    i += len;
+                    continue;
+                }
+                Instruction::OutputData => instructions.push(at(Op::Output)),
+                Instruction::AcceptData => instructions.push(at(Op::Input)),
+                Instruction::JumpForwardsIfZero => {
+                    let end = loaded.partner.expect("scripts have matched brackets");
+                    let optimised = wrapping
+                        .then(|| optimise_loop(&source[i + 1..end], circular))
+                        .flatten();
+                    if let Some(ops) = optimised {
+                        instructions.extend(ops.into_iter().map(at));
+                        i = end + 1;
+                        continue;
+                    }
+                    open_loops.push(instructions.len());
+                    instructions.push(at(Op::JumpIfZero(0)));
+                }
+                Instruction::JumpBackwardsIfNonzero => {
+                    let open = open_loops.pop().expect("scripts have matched brackets");
+                    instructions[open].op = Op::JumpIfZero(instructions.len());
+                    instructions.push(at(Op::JumpIfNonzero(open)));
+                }
+            }
+            i += 1;
+        }
+        Self {
+            instructions,
+            source: source.clone(),
+        }
+    }
+
+    pub fn run<T: CellType>(&self, context: &mut RuntimeContext<T>) -> Result<(), RuntimeError> {
+        run(self, context)
+    }
+}
+
+/// Code that [`run`] can execute, so that the IR and the bytecode share one interpreter.
+pub(crate) trait Code {
+    fn len(&self) -> usize;
+    /// The operation at `index`, with jump targets as indices into the code.
+    fn op(&self, index: usize) -> Op;
+    /// The script instruction that the operation at `index` was built from.
+    fn source(&self, index: usize) -> LoadedInstruction;
+}
+impl Code for Program {
+    fn len(&self) -> usize {
+        self.instructions.len()
+    }
+    fn op(&self, index: usize) -> Op {
+        self.instructions[index].op
+    }
+    fn source(&self, index: usize) -> LoadedInstruction {
+        self.source[self.instructions[index].source_index]
+    }
+}
+
+pub(crate) fn run<T: CellType>(
+    code: &impl Code,
+    context: &mut RuntimeContext<T>,
+) -> Result<(), RuntimeError> {
+    let mut ip = 0;
+    // Cycles are only counted when there are limits to check them against.
+    let limited = context.has_run_limits();
+    let mut cycles = 0usize;
+    while ip < code.len() {
+        let error = |kind| RuntimeError::new(kind, code.source(ip), None);
+        let overflow = |amount| error(RuntimeErrorKind::overflow(amount));
+        if limited {
+            context.check_limits(cycles).map_err(error)?;
+            cycles += 1;
+        }
+        match code.op(ip) {
+            Op::Add(amount) => context
+                .add_to_cell(context.data_pointer, amount as i128)
+                .map_err(|_| overflow(amount as i128))?,
+            Op::Move(amount) => context
+                .move_data_pointer(amount)
+                .map_err(|e| error(e.into()))?,
+            Op::Output => context
+                .write(context.read_cell(context.data_pointer))
+                .map_err(|e| error(e.into()))?,
+            Op::Input => {
+                context
+                    .read_to_cell(context.data_pointer)
+                    .map_err(|e| error(e.into()))?;
+            }
+            Op::JumpIfZero(target) => {
+                if context.cell_is_zero(context.data_pointer) {
+                    ip = target;
+                }
+            }
+            Op::JumpIfNonzero(target) => {
+                if !context.cell_is_zero(context.data_pointer) {
+                    ip = target;
+                }
+            }
+            Op::Clear => *context.get_cell(context.data_pointer) = T::zero(),
+            Op::MulAdd { offset, factor } => {
+                let value = context.read_cell(context.data_pointer).to_i128();
+                if value != 0 {
+                    let target = context.relative_cell(offset).map_err(|e| error(e.into()))?;
+                    let amount = value * factor as i128;
+                    context
+                        .add_to_cell(target, amount)
+                        .map_err(|_| overflow(amount))?;
+                }
+            }
+            Op::Scan(step) => {
+                while !context.cell_is_zero(context.data_pointer) {
+                    if limited {
+                        context.check_limits(cycles).map_err(error)?;
+                        cycles += 1;
+                    }
+                    context
+                        .move_data_pointer(step)
+                        .map_err(|e| error(e.into()))?;
+                }
+            }
+        }
+        ip += 1;
+    }
+    Ok(())
+}
+
+/// Returns the total of a run of `up`/`down` instructions and its length.
+/// If the run can't be `mixed`, it ends where it would change direction.
+fn fold_run(
+    source: &[LoadedInstruction],
+    up: Instruction,
+    down: Instruction,
+    mixed: bool,
+) -> (isize, usize) {
+    let mut amount = 0isize;
+    let mut len = 0;
+    for loaded in source {
+        let step = if loaded.instruction == up {
+            1
+        } else if loaded.instruction == down {
+            -1
+        } else {
+            break;
+        };
+        if !mixed && amount != 0 && amount.signum() != step {
+            break;
+        }
+        amount += step;
+        len += 1;
+    }
+    (amount, len)
+}
+
+fn optimise_loop(body: &[LoadedInstruction], circular: bool) -> Option<Vec<Op>> {
+    let mut offset = 0isize;
+    let (mut lowest, mut highest) = (0isize, 0isize);
+    let mut deltas = BTreeMap::<isize, isize>::new();
+    for loaded in body {
+        match loaded.instruction {
+            Instruction::IncrementDataPointer => offset += 1,
+            Instruction::DecrementDataPointer => offset -= 1,
+            Instruction::IncrementData => *deltas.entry(offset).or_default() += 1,
+            Instruction::DecrementData => *deltas.entry(offset).or_default() -= 1,
+            _ => return None,
+        }
+        lowest = lowest.min(offset);
+        highest = highest.max(offset);
+    }
+    deltas.retain(|_, delta| *delta != 0);
+
+    if deltas.is_empty() {
+        // A scan only checks the edges of the tape where each iteration ends.
+        let stays_in_range = lowest == offset.min(0) && highest == offset.max(0);
+        return if offset != 0 && (circular || stays_in_range) {
+            Some(vec![Op::Scan(offset)])
+        } else {
+            None
+        };
+    }
+    if offset != 0 {
+        return None;
+    }
+    // Multiplications only check the edges of the tape at the cells they change.
+    let reached = |edge| edge == 0 || deltas.contains_key(&edge);
+    if !circular && (!reached(lowest) || !reached(highest)) {
+        return None;
+    }
+    // The loop only runs a known number of times if the current cell changes by exactly one.
+    let direction = match deltas.remove(&0) {
+        Some(-1) => 1,
+        Some(1) => -1,
+        _ => return None,
+    };
+    let mut ops: Vec<Op> = deltas
+        .into_iter()
+        .map(|(offset, delta)| Op::MulAdd {
+            offset,
+            factor: delta * direction,
+        })
+        .collect();
+    ops.push(Op::Clear);
+    Some(ops)
+}
@@ -0,0 +1,548 @@
+//! Just-in-time compilation of the [IR](crate::ir) to machine code with Cranelift.
+//!
+//! The generated code works on the tape of a [`RuntimeContext`] directly and calls back into
+//! the context for input, output and growing the tape. Only left-bounded tapes are supported.
+use crate::ir::{self, Op};
+use crate::{
+    CellType, LoadedInstruction, OverflowMode, RuntimeContext, RuntimeError, RuntimeErrorKind,
+    TapeError, TapePolicy,
+};
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{types, AbiParam, Block, FuncRef, InstBuilder, MemFlags, Type, Value};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Linkage, Module};
+use std::any::Any;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::mem::{offset_of, size_of};
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+
+#[derive(Debug)]
+pub enum JitError {
+    /// The cell type, cell bounds or overflow mode of the context can't be handled by generated
+    /// code.
+    UnsupportedCells,
+    /// The tape policy of the context can't be handled by generated code.
+    UnsupportedTape,
+    /// The context has a cycle or time limit, which generated code doesn't check.
+    UnsupportedLimits,
+    /// Cranelift could not generate code for this machine.
+    Codegen(String),
+}
+impl Display for JitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JitError::UnsupportedCells => f.write_str(
+                "only cells wrapping around at the bounds of an integer type are supported",
+            ),
+            JitError::UnsupportedTape => f.write_str("only left-bounded tapes are supported"),
+            JitError::UnsupportedLimits => f.write_str("cycle and time limits are not supported"),
+            JitError::Codegen(message) => write!(f, "could not generate code: {}", message),
+        }
+    }
+}
+impl Error for JitError {}
+
+/// What the generated code shares with the functions it calls back into.
+/// The tape fields mirror `context.data` and are read again after every call that may resize it.
+struct JitState<'a, T: CellType> {
+    tape: *mut T,
+    tape_len: usize,
+    data_pointer: usize,
+    context: &'a mut RuntimeContext<T>,
+    source: &'a [LoadedInstruction],
+    failure: Option<Failure>,
+}
+impl<T: CellType> JitState<'_, T> {
+    fn fail(&mut self, kind: RuntimeErrorKind, source_index: usize) {
+        let error = RuntimeError::new(kind, self.source[source_index], None);
+        self.failure = Some(Failure::Error(error));
+    }
+}
+
+/// Why the generated code stopped early.
+enum Failure {
+    Error(RuntimeError),
+    /// A callback panicked. The panic is resumed once the generated code returns.
+    Panic(Box<dyn Any + Send>),
+}
+
+type CompiledFn<T> = unsafe extern "C" fn(*mut JitState<'_, T>) -> i8;
+
+/// A program compiled to machine code for one cell type.
+/// The generated code works on cells in memory directly, so they have to be plain integers.
+pub struct JitProgram<T: CellType + Copy> {
+    module: Option<JITModule>,
+    function: CompiledFn<T>,
+    source: Vec<LoadedInstruction>,
+    _cell: PhantomData<T>,
+}
+
+impl<T: CellType + Copy> JitProgram<T> {
+    /// Whether programs can be compiled for `context`.
+    pub fn check_support(context: &RuntimeContext<T>) -> Result<(), JitError> {
+        if cell_type::<T>().is_none()
+            || context.min_cell_value != T::min_value()
+            || context.max_cell_value != T::max_value()
+        {
+            return Err(JitError::UnsupportedCells);
+        }
+        if context.overflow_mode != OverflowMode::Wrap {
+            return Err(JitError::UnsupportedCells);
+        }
+        if context.tape_policy != TapePolicy::LeftBounded {
+            return Err(JitError::UnsupportedTape);
+        }
+        if context.has_run_limits() {
+            return Err(JitError::UnsupportedLimits);
+        }
+        Ok(())
+    }
+
+    /// Compiles a program that can run on `context`, or on any context with the same cell bounds.
+    pub fn compile(program: &ir::Program, context: &RuntimeContext<T>) -> Result<Self, JitError> {
+        Self::check_support(context)?;
+        let cell_type = cell_type::<T>().ok_or(JitError::UnsupportedCells)?;
+
+        let codegen = |e: &dyn Display| JitError::Codegen(e.to_string());
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed").map_err(|e| codegen(&e))?;
+        flags.set("is_pic", "false").map_err(|e| codegen(&e))?;
+        let isa = cranelift_native::builder()
+            .map_err(|e| codegen(&e))?
+            .finish(settings::Flags::new(flags))
+            .map_err(|e| codegen(&e))?;
+        let mut builder = JITBuilder::with_isa(isa, default_libcall_names());
+        builder.symbol("bf_grow", grow::<T> as *const u8);
+        builder.symbol("bf_input", input::<T> as *const u8);
+        builder.symbol("bf_output", output::<T> as *const u8);
+        let mut module = JITModule::new(builder);
+
+        let pointer_type = module.target_config().pointer_type();
+        // Every callback takes the state, a cell index and the index of the source instruction
+        // to report errors at, and returns 0 on success.
+        let mut callback = module.make_signature();
+        callback.params.push(AbiParam::new(pointer_type));
+        callback.params.push(AbiParam::new(pointer_type));
+        callback.params.push(AbiParam::new(pointer_type));
+        callback.returns.push(AbiParam::new(types::I8));
+        let mut declare_callback = |name| {
+            module
+                .declare_function(name, Linkage::Import, &callback)
+                .map_err(|e| codegen(&e))
+        };
+        let callbacks = [
+            declare_callback("bf_grow")?,
+            declare_callback("bf_input")?,
+            declare_callback("bf_output")?,
+        ];
+
+        let mut ctx = module.make_context();
+        ctx.func.signature.params.push(AbiParam::new(pointer_type));
+        ctx.func.signature.returns.push(AbiParam::new(types::I8));
+        let function_id = module
+            .declare_function("bf_main", Linkage::Local, &ctx.func.signature)
+            .map_err(|e| codegen(&e))?;
+
+        let mut function_context = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut ctx.func, &mut function_context);
+        let [grow_ref, input_ref, output_ref] =
+            callbacks.map(|id| module.declare_func_in_func(id, builder.func));
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        let state = builder.block_params(entry)[0];
+        Translator {
+            builder: &mut builder,
+            pointer_type,
+            cell_type,
+            state,
+            pointer: Variable::from_u32(0),
+            tape: Variable::from_u32(1),
+            tape_len: Variable::from_u32(2),
+            grow: grow_ref,
+            input: input_ref,
+            output: output_ref,
+            offsets: StateOffsets::of::<T>(),
+        }
+        .translate(program);
+        builder.finalize();
+
+        module
+            .define_function(function_id, &mut ctx)
+            .map_err(|e| codegen(&e))?;
+        module.clear_context(&mut ctx);
+        module.finalize_definitions().map_err(|e| codegen(&e))?;
+        let code = module.get_finalized_function(function_id);
+        // SAFETY: the function was declared with this signature above.
+        let function = unsafe { std::mem::transmute::<*const u8, CompiledFn<T>>(code) };
+        Ok(Self {
+            module: Some(module),
+            function,
+            source: program.source.clone(),
+            _cell: PhantomData,
+        })
+    }
+
+    pub fn run(&self, context: &mut RuntimeContext<T>) -> Result<(), RuntimeError> {
+        assert!(
+            context.min_cell_value == T::min_value()
+                && context.max_cell_value == T::max_value()
+                && context.overflow_mode == OverflowMode::Wrap,
+            "cell settings changed since the program was compiled"
+        );
+        // The generated code expects the data pointer to be on the tape.
+        context.get_cell(context.data_pointer);
+        let mut state = JitState {
+            tape: context.data.as_mut_ptr(),
+            tape_len: context.data.len(),
+            data_pointer: context.data_pointer,
+            context,
+            source: &self.source,
+            failure: None,
+        };
+        // SAFETY: the state points at the tape of the context, and the generated code stays
+        // within `tape_len` cells of it.
+        let status = unsafe { (self.function)(&mut state) };
+        let JitState {
+            data_pointer,
+            context,
+            failure,
+            ..
+        } = state;
+        context.data_pointer = data_pointer;
+
+        match (status, failure) {
+            (0, _) => Ok(()),
+            (_, Some(Failure::Error(error))) => Err(error),
+            (_, Some(Failure::Panic(payload))) => resume_unwind(payload),
+            (_, None) => unreachable!("generated code failed without a reason"),
+        }
+    }
+}
+
+fn cell_type<T>() -> Option<Type> {
+    match size_of::<T>() {
+        1 => Some(types::I8),
+        2 => Some(types::I16),
+        4 => Some(types::I32),
+        8 => Some(types::I64),
+        _ => None,
+    }
+}
+
+impl<T: CellType + Copy> Drop for JitProgram<T> {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            // SAFETY: `function` can't be called any more once the program is dropped.
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+struct StateOffsets {
+    tape: i32,
+    tape_len: i32,
+    data_pointer: i32,
+}
+impl StateOffsets {
+    fn of<T: CellType>() -> Self {
+        Self {
+            tape: offset_of!(JitState<'static, T>, tape) as i32,
+            tape_len: offset_of!(JitState<'static, T>, tape_len) as i32,
+            data_pointer: offset_of!(JitState<'static, T>, data_pointer) as i32,
+        }
+    }
+}
+
+/// Translates IR into a Cranelift function taking a `JitState`.
+///
+/// The data pointer, tape address and tape length are kept in variables, and the data pointer
+/// is always kept below the tape length so cells can be accessed without further checks.
+struct Translator<'a, 'b> {
+    builder: &'a mut FunctionBuilder<'b>,
+    pointer_type: Type,
+    cell_type: Type,
+    state: Value,
+    pointer: Variable,
+    tape: Variable,
+    tape_len: Variable,
+    grow: FuncRef,
+    input: FuncRef,
+    output: FuncRef,
+    offsets: StateOffsets,
+}
+
+impl Translator<'_, '_> {
+    /// Translates the program into the current block, which should be the entry block.
+    fn translate(mut self, program: &ir::Program) {
+        let fail = self.builder.create_block();
+        self.builder.declare_var(self.pointer, self.pointer_type);
+        self.builder.declare_var(self.tape, self.pointer_type);
+        self.builder.declare_var(self.tape_len, self.pointer_type);
+        let pointer = self.load_state(self.offsets.data_pointer);
+        self.builder.def_var(self.pointer, pointer);
+        self.reload_tape();
+
+        // Body and exit blocks of the loops that are currently open.
+        let mut loops = Vec::new();
+        for instruction in &program.instructions {
+            match instruction.op {
+                Op::Add(amount) => {
+                    let pointer = self.builder.use_var(self.pointer);
+                    self.add_to_cell(pointer, |t| t.cell_const(amount as i64));
+                }
+                Op::Move(amount) => {
+                    let pointer = self.builder.use_var(self.pointer);
+                    let pointer = self.builder.ins().iadd_imm(pointer, amount as i64);
+                    self.ensure_in_tape(pointer, instruction.source_index, fail);
+                    self.builder.def_var(self.pointer, pointer);
+                }
+                Op::Output => self.call_with_pointer(self.output, instruction.source_index, fail),
+                Op::Input => self.call_with_pointer(self.input, instruction.source_index, fail),
+                Op::JumpIfZero(_) => {
+                    let body = self.builder.create_block();
+                    let exit = self.builder.create_block();
+                    let value = self.current_cell();
+                    self.builder.ins().brif(value, body, &[], exit, &[]);
+                    self.builder.switch_to_block(body);
+                    loops.push((body, exit));
+                }
+                Op::JumpIfNonzero(_) => {
+                    let (body, exit) = loops.pop().expect("brackets should be balanced");
+                    let value = self.current_cell();
+                    self.builder.ins().brif(value, body, &[], exit, &[]);
+                    self.builder.switch_to_block(exit);
+                }
+                Op::Clear => {
+                    let pointer = self.builder.use_var(self.pointer);
+                    let address = self.cell_address(pointer);
+                    let zero = self.cell_const(0);
+                    self.builder
+                        .ins()
+                        .store(MemFlags::trusted(), zero, address, 0);
+                }
+                Op::MulAdd { offset, factor } => {
+                    let apply = self.builder.create_block();
+                    let done = self.builder.create_block();
+                    let value = self.current_cell();
+                    self.builder.ins().brif(value, apply, &[], done, &[]);
+                    self.builder.switch_to_block(apply);
+                    let pointer = self.builder.use_var(self.pointer);
+                    let target = self.builder.ins().iadd_imm(pointer, offset as i64);
+                    self.ensure_in_tape(target, instruction.source_index, fail);
+                    self.add_to_cell(target, |t| {
+                        let factor = t.cell_const(factor as i64);
+                        t.builder.ins().imul(value, factor)
+                    });
+                    self.builder.ins().jump(done, &[]);
+                    self.builder.switch_to_block(done);
+                }
+                Op::Scan(step) => {
+                    let check = self.builder.create_block();
+                    let step_block = self.builder.create_block();
+                    let done = self.builder.create_block();
+                    self.builder.ins().jump(check, &[]);
+                    self.builder.switch_to_block(check);
+                    let value = self.current_cell();
+                    self.builder.ins().brif(value, step_block, &[], done, &[]);
+                    self.builder.switch_to_block(step_block);
+                    let pointer = self.builder.use_var(self.pointer);
+                    let pointer = self.builder.ins().iadd_imm(pointer, step as i64);
+                    self.ensure_in_tape(pointer, instruction.source_index, fail);
+                    self.builder.def_var(self.pointer, pointer);
+                    self.builder.ins().jump(check, &[]);
+                    self.builder.switch_to_block(done);
+                }
+            }
+        }
+        self.exit(0);
+
+        self.builder.switch_to_block(fail);
+        self.exit(1);
+        self.builder.seal_all_blocks();
+    }
+
+    /// Stores the data pointer back into the state and returns `status`.
+    fn exit(&mut self, status: i64) {
+        let pointer = self.builder.use_var(self.pointer);
+        self.builder.ins().store(
+            MemFlags::trusted(),
+            pointer,
+            self.state,
+            self.offsets.data_pointer,
+        );
+        let status = self.builder.ins().iconst(types::I8, status);
+        self.builder.ins().return_(&[status]);
+    }
+
+    fn load_state(&mut self, offset: i32) -> Value {
+        self.builder
+            .ins()
+            .load(self.pointer_type, MemFlags::trusted(), self.state, offset)
+    }
+    fn reload_tape(&mut self) {
+        let tape = self.load_state(self.offsets.tape);
+        self.builder.def_var(self.tape, tape);
+        let tape_len = self.load_state(self.offsets.tape_len);
+        self.builder.def_var(self.tape_len, tape_len);
+    }
+
+    /// Grows the tape if `index` is past the end of it.
+    /// Negative indices wrap around to huge ones, which makes the callback fail.
+    fn ensure_in_tape(&mut self, index: Value, source_index: usize, fail: Block) {
+        let grow = self.builder.create_block();
+        let grown = self.builder.create_block();
+        let done = self.builder.create_block();
+        let tape_len = self.builder.use_var(self.tape_len);
+        let outside = self
+            .builder
+            .ins()
+            .icmp(IntCC::UnsignedGreaterThanOrEqual, index, tape_len);
+        self.builder.ins().brif(outside, grow, &[], done, &[]);
+
+        self.builder.switch_to_block(grow);
+        let status = self.call(self.grow, index, source_index);
+        self.builder.ins().brif(status, fail, &[], grown, &[]);
+
+        self.builder.switch_to_block(grown);
+        self.reload_tape();
+        self.builder.ins().jump(done, &[]);
+        self.builder.switch_to_block(done);
+    }
+
+    fn call_with_pointer(&mut self, function: FuncRef, source_index: usize, fail: Block) {
+        let pointer = self.builder.use_var(self.pointer);
+        let status = self.call(function, pointer, source_index);
+        let done = self.builder.create_block();
+        self.builder.ins().brif(status, fail, &[], done, &[]);
+        self.builder.switch_to_block(done);
+    }
+
+    /// Calls a callback and returns its status.
+    fn call(&mut self, function: FuncRef, index: Value, source_index: usize) -> Value {
+        let source_index = self
+            .builder
+            .ins()
+            .iconst(self.pointer_type, source_index as i64);
+        let call = self
+            .builder
+            .ins()
+            .call(function, &[self.state, index, source_index]);
+        self.builder.inst_results(call)[0]
+    }
+
+    fn cell_address(&mut self, index: Value) -> Value {
+        let tape = self.builder.use_var(self.tape);
+        let size = self.cell_type.bytes() as i64;
+        let offset = self.builder.ins().imul_imm(index, size);
+        self.builder.ins().iadd(tape, offset)
+    }
+    fn current_cell(&mut self) -> Value {
+        let pointer = self.builder.use_var(self.pointer);
+        let address = self.cell_address(pointer);
+        self.builder
+            .ins()
+            .load(self.cell_type, MemFlags::trusted(), address, 0)
+    }
+    /// Adds the value computed by `amount` to the cell at `index`, wrapping around.
+    fn add_to_cell(&mut self, index: Value, amount: impl FnOnce(&mut Self) -> Value) {
+        let address = self.cell_address(index);
+        let value = self
+            .builder
+            .ins()
+            .load(self.cell_type, MemFlags::trusted(), address, 0);
+        let amount = amount(self);
+        let sum = self.builder.ins().iadd(value, amount);
+        self.builder
+            .ins()
+            .store(MemFlags::trusted(), sum, address, 0);
+    }
+    /// A constant of the cell type, truncated to its width.
+    fn cell_const(&mut self, value: i64) -> Value {
+        let bits = self.cell_type.bits();
+        let value = if bits < 64 {
+            value & ((1i64 << bits) - 1)
+        } else {
+            value
+        };
+        self.builder.ins().iconst(self.cell_type, value)
+    }
+}
+
+/// Makes room for the cell at `index`, growing the tape geometrically up to its maximum length.
+extern "C" fn grow<T: CellType>(
+    state: *mut JitState<'_, T>,
+    index: usize,
+    source_index: usize,
+) -> i8 {
+    // SAFETY: the generated code passes on the state it was called with.
+    let state = unsafe { &mut *state };
+    let limit = state.context.max_tape_length;
+    let error = if index > isize::MAX as usize / size_of::<T>() {
+        Some(TapeError::Underflow)
+    } else {
+        match limit {
+            Some(limit) if index >= limit => Some(TapeError::LimitExceeded { limit }),
+            _ => None,
+        }
+    };
+    if let Some(error) = error {
+        state.fail(error.into(), source_index);
+        return 1;
+    }
+    let data = &mut state.context.data;
+    let len = (index + 1)
+        .max(data.len() * 2)
+        .min(limit.unwrap_or(usize::MAX));
+    data.resize(len, T::zero());
+    state.tape = data.as_mut_ptr();
+    state.tape_len = data.len();
+    0
+}
+
+extern "C" fn input<T: CellType>(
+    state: *mut JitState<'_, T>,
+    index: usize,
+    source_index: usize,
+) -> i8 {
+    // SAFETY: the generated code passes on the state it was called with.
+    let state = unsafe { &mut *state };
+    guard(state, source_index, |context| {
+        context
+            .read_to_cell(index)
+            .map_err(RuntimeErrorKind::from)?;
+        Ok(())
+    })
+}
+
+extern "C" fn output<T: CellType>(
+    state: *mut JitState<'_, T>,
+    index: usize,
+    source_index: usize,
+) -> i8 {
+    // SAFETY: the generated code passes on the state it was called with.
+    let state = unsafe { &mut *state };
+    guard(state, source_index, |context| {
+        context
+            .write(context.read_cell(index))
+            .map_err(RuntimeErrorKind::from)
+    })
+}
+
+/// Runs `f`, catching any panic so it doesn't unwind through generated code.
+fn guard<T: CellType>(
+    state: &mut JitState<'_, T>,
+    source_index: usize,
+    f: impl FnOnce(&mut RuntimeContext<T>) -> Result<(), RuntimeErrorKind>,
+) -> i8 {
+    match catch_unwind(AssertUnwindSafe(|| f(state.context))) {
+        Ok(Ok(())) => return 0,
+        Ok(Err(kind)) => state.fail(kind, source_index),
+        Err(payload) => state.failure = Some(Failure::Panic(payload)),
+    }
+    1
+}
@@ -0,0 +1,46 @@
+use crate::{CellType, LoadedInstruction, RuntimeContext, RuntimeError, Script};
+
+/// Receives events from the step engine while a script runs, for tracing, profiling, coverage
+/// or user interfaces. Observers are added to a context with
+/// [`RuntimeContext::add_observer`] or [`RuntimeContextBuilder::observer`](crate::RuntimeContextBuilder::observer).
+///
+/// The events of an instruction are sent once it has run successfully, with
+/// [`instruction_executed`](Self::instruction_executed) last. The optimised engines don't send
+/// any events. Every method does nothing unless it is implemented.
+#[allow(unused_variables)]
+pub trait ExecutionObserver<T: CellType> {
+    /// The cell at `index` changed from `old` to `new` because of `+`, `-` or `,`.
+    fn cell_changed(&mut self, index: usize, old: &T, new: &T) {}
+    /// The data pointer moved from `old` to `new`.
+    /// Both are indices into the data as it is after the move, which only matters for
+    /// bidirectional tapes that grew to the left.
+    fn pointer_moved(&mut self, old: usize, new: usize) {}
+    /// `,` read `value` from the input, or `None` at the end of it.
+    fn input_read(&mut self, value: Option<&T>) {}
+    /// `.` wrote `value` to the output.
+    fn output_written(&mut self, value: &T) {}
+    /// The `[` with the index `start` found a nonzero cell and went into its loop.
+    fn loop_entered(&mut self, start: usize) {}
+    /// A `]` went back into the loop whose `[` has the index `start`.
+    fn loop_repeated(&mut self, start: usize) {}
+    /// A `]` found a zero cell and left the loop whose `[` has the index `start`.
+    /// A `[` that skips its loop jumps to the `]`, so skipped loops are exited without being
+    /// entered.
+    fn loop_exited(&mut self, start: usize) {}
+    /// `instruction` ran, and `script` has moved on to the next one.
+    fn instruction_executed(
+        &mut self,
+        script: &Script,
+        context: &RuntimeContext<T>,
+        instruction: LoadedInstruction,
+    ) {
+    }
+    /// The script ran its last instruction, or stopped because of `error`.
+    fn halted(
+        &mut self,
+        script: &Script,
+        context: &RuntimeContext<T>,
+        error: Option<&RuntimeError>,
+    ) {
+    }
+}
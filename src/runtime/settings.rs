@@ -0,0 +1,123 @@
+/// Declares an enum of settings that can be parsed from and displayed as their names.
+/// Exported so that the interactive mode can declare its own settings the same way.
+#[macro_export]
+macro_rules! setting_enum {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident ($description:literal) {
+            $( $(#[$variant_meta:meta])* $variant:ident => $str:literal ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Copy, Clone, Eq, PartialEq, Debug)]
+        pub enum $name {
+            $( $(#[$variant_meta])* $variant ),*
+        }
+        impl $name {
+            pub const NAMES: &'static [&'static str] = &[$($str),*];
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $( Self::$variant => $str ),*
+                }
+            }
+        }
+        impl ::std::fmt::Display for $name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                f.write_str(self.name())
+            }
+        }
+        impl ::std::str::FromStr for $name {
+            type Err = ::std::string::String;
+
+            fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+                $(
+                if s.eq_ignore_ascii_case($str) {
+                    return ::std::result::Result::Ok(Self::$variant);
+                }
+                )*
+                ::std::result::Result::Err(::std::format!(
+                    "unknown {} '{}' (expected one of: {})",
+                    $description,
+                    s,
+                    Self::NAMES.join(", ")
+                ))
+            }
+        }
+    };
+}
+
+setting_enum! {
+    /// The integer types that can be used for cells in compiled programs.
+    #[derive(Default)]
+    pub enum CellKind("cell type") {
+        #[default]
+        U8 => "u8",
+        U16 => "u16",
+        U32 => "u32",
+        U64 => "u64",
+    }
+}
+
+setting_enum! {
+    /// The cell types scripts can be run with.
+    #[derive(Default)]
+    pub enum RuntimeCellKind("cell type") {
+        #[default]
+        U8 => "u8",
+        U16 => "u16",
+        U32 => "u32",
+        U64 => "u64",
+        I8 => "i8",
+        I16 => "i16",
+        I32 => "i32",
+        I64 => "i64",
+        /// Arbitrary-precision integers that never wrap around.
+        Bignum => "bignum",
+    }
+}
+
+setting_enum! {
+    /// What happens when a script reads input after the end of it.
+    #[derive(Default)]
+    pub enum EofBehaviour("EOF behaviour") {
+        /// Store 0 in the cell.
+        #[default]
+        Zero => "zero",
+        /// Store -1 in the cell, i.e. the maximum value for unsigned cells.
+        MinusOne => "minus-one",
+        /// Leave the cell as it is.
+        Unchanged => "unchanged",
+        /// Stop execution with an error.
+        Error => "error",
+    }
+}
+
+setting_enum! {
+    /// What the tape looks like at its edges.
+    #[derive(Default)]
+    pub enum TapePolicy("tape policy") {
+        /// The tape starts at the first cell and extends to the right.
+        /// Moving left of the first cell is an error.
+        #[default]
+        LeftBounded => "left-bounded",
+        /// The tape extends in both directions.
+        Bidirectional => "bidirectional",
+        /// The tape wraps around, so moving past one end continues at the other.
+        Circular => "circular",
+    }
+}
+
+setting_enum! {
+    /// What happens when a cell goes past its minimum or maximum value.
+    #[derive(Default)]
+    pub enum OverflowMode("overflow mode") {
+        /// Wrap around to the other bound.
+        #[default]
+        Wrap => "wrap",
+        /// Stay at the bound.
+        Saturate => "saturate",
+        /// Stop execution with an error.
+        Trap => "trap",
+    }
+}
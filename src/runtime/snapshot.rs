@@ -0,0 +1,480 @@
+use crate::{
+    CellType, EofBehaviour, OverflowMode, ParseError, RuntimeContext, Script, TapePolicy,
+    DEFAULT_CIRCULAR_TAPE_LENGTH,
+};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+const MAGIC: &str = "brainfuck snapshot";
+
+/// The state of a running script, which can be saved to a file and resumed later.
+///
+/// Snapshots are written in a small text format: a header with the format version, one
+/// `name value` line for every setting and the source, input and output as
+/// length-prefixed blocks of bytes. Cells are stored as decimal numbers, so a snapshot can be
+/// loaded with any cell type that can hold its values.
+///
+/// Neither the limits of the context nor the functions it reads and writes with are part of
+/// the snapshot, since they belong to whoever runs the script rather than to the script.
+#[derive(Clone, Debug)]
+pub struct Snapshot<T: CellType> {
+    pub source: String,
+    pub instruction_pointer: usize,
+    pub cycles: usize,
+    pub data: Vec<T>,
+    pub data_pointer: usize,
+    pub origin: usize,
+    pub tape_policy: TapePolicy,
+    pub max_tape_length: Option<usize>,
+    pub min_cell_value: T,
+    pub max_cell_value: T,
+    pub overflow_mode: OverflowMode,
+    pub eof_behaviour: EofBehaviour,
+    /// Input that the script hasn't read yet.
+    pub input: Vec<u8>,
+    /// Output the script has written so far.
+    pub output: Vec<u8>,
+}
+
+/// Why a snapshot could not be loaded.
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// The snapshot was written in a version of the format this build doesn't know.
+    UnsupportedVersion(u32),
+    /// The file isn't a snapshot, or it is damaged.
+    Invalid(String),
+    /// The source stored in the snapshot doesn't load.
+    Script(ParseError),
+}
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "I/O error: {}", error),
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported snapshot version {} (expected {})",
+                version,
+                Snapshot::<u8>::VERSION
+            ),
+            SnapshotError::Invalid(message) => write!(f, "invalid snapshot: {}", message),
+            SnapshotError::Script(error) => write!(f, "invalid script in snapshot: {}", error),
+        }
+    }
+}
+impl Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SnapshotError::Io(error) => Some(error),
+            SnapshotError::Script(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl<T: CellType> Snapshot<T> {
+    /// Version of the file format written by [`write_to`](Self::write_to).
+    pub const VERSION: u32 = 1;
+
+    /// Takes a snapshot of `script` and `context`.
+    /// The context can't see its input and output, so they have to be passed in separately.
+    pub fn capture(
+        script: &Script,
+        context: &RuntimeContext<T>,
+        input: Vec<u8>,
+        output: Vec<u8>,
+    ) -> Self {
+        // The tape grows lazily, so make sure the current cell is part of it.
+        let mut data = context.data.clone();
+        if data.len() <= context.data_pointer {
+            data.resize(context.data_pointer + 1, T::zero());
+        }
+        Self {
+            source: script.source.clone(),
+            instruction_pointer: script.instruction_pointer,
+            cycles: script.cycles,
+            data,
+            data_pointer: context.data_pointer,
+            origin: context.origin,
+            tape_policy: context.tape_policy,
+            max_tape_length: context.max_tape_length,
+            min_cell_value: context.min_cell_value.clone(),
+            max_cell_value: context.max_cell_value.clone(),
+            overflow_mode: context.overflow_mode,
+            eof_behaviour: context.eof_behaviour,
+            input,
+            output,
+        }
+    }
+
+    /// Loads the script, with the instruction pointer and cycles where they were.
+    pub fn script(&self) -> Result<Script, SnapshotError> {
+        let mut script = Script::new(self.source.clone()).map_err(SnapshotError::Script)?;
+        if self.instruction_pointer > script.instructions.len() {
+            return Err(SnapshotError::Invalid(format!(
+                "instruction pointer {} is past the end of the script",
+                self.instruction_pointer
+            )));
+        }
+        script.instruction_pointer = self.instruction_pointer;
+        script.cycles = self.cycles;
+        Ok(script)
+    }
+
+    /// Puts the tape and settings of the snapshot into `context`.
+    /// The output written so far counts towards the output limit of the context.
+    pub fn restore(&self, context: &mut RuntimeContext<T>) {
+        context.data = self.data.clone();
+        context.data_pointer = self.data_pointer;
+        context.origin = self.origin;
+        context.tape_policy = self.tape_policy;
+        context.max_tape_length = self.max_tape_length;
+        context.min_cell_value = self.min_cell_value.clone();
+        context.max_cell_value = self.max_cell_value.clone();
+        context.overflow_mode = self.overflow_mode;
+        context.eof_behaviour = self.eof_behaviour;
+        context.output_len = self.output.len();
+    }
+
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "{} {}", MAGIC, Self::VERSION)?;
+        writeln!(writer, "instruction-pointer {}", self.instruction_pointer)?;
+        writeln!(writer, "cycles {}", self.cycles)?;
+        writeln!(writer, "data-pointer {}", self.data_pointer)?;
+        writeln!(writer, "origin {}", self.origin)?;
+        writeln!(writer, "tape-policy {}", self.tape_policy)?;
+        match self.max_tape_length {
+            Some(length) => writeln!(writer, "max-tape-length {}", length)?,
+            None => writeln!(writer, "max-tape-length none")?,
+        }
+        if T::BITS.is_some() {
+            let (min, max) = (&self.min_cell_value, &self.max_cell_value);
+            writeln!(writer, "cell-bounds {} {}", min, max)?;
+        } else {
+            writeln!(writer, "cell-bounds none")?;
+        }
+        writeln!(writer, "overflow {}", self.overflow_mode)?;
+        writeln!(writer, "eof {}", self.eof_behaviour)?;
+        write!(writer, "tape")?;
+        for value in &self.data {
+            write!(writer, " {}", value)?;
+        }
+        writeln!(writer)?;
+        write_block(&mut writer, "source", self.source.as_bytes())?;
+        write_block(&mut writer, "input", &self.input)?;
+        write_block(&mut writer, "output", &self.output)?;
+        writer.flush()
+    }
+
+    pub fn read_from(mut reader: impl Read) -> Result<Self, SnapshotError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let mut parser = Parser { rest: &bytes };
+
+        let header = parser.line()?;
+        let version = header
+            .strip_prefix(MAGIC)
+            .and_then(|v| v.strip_prefix(' '))
+            .ok_or_else(|| SnapshotError::Invalid("not a snapshot file".to_owned()))?;
+        let version: u32 = parse_value("version", version)?;
+        if version != Self::VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let instruction_pointer = parser.value("instruction-pointer")?;
+        let cycles = parser.value("cycles")?;
+        let data_pointer = parser.value("data-pointer")?;
+        let origin = parser.value("origin")?;
+        let tape_policy = parser.value("tape-policy")?;
+        let max_tape_length = match parser.field("max-tape-length")? {
+            "none" => None,
+            length => Some(parse_value("max-tape-length", length)?),
+        };
+        let (min_cell_value, max_cell_value) = match parser.field("cell-bounds")? {
+            "none" => (T::min_value(), T::max_value()),
+            bounds => {
+                let (min, max) = bounds.split_once(' ').ok_or_else(|| {
+                    SnapshotError::Invalid(format!("invalid value '{}' for cell-bounds", bounds))
+                })?;
+                let (min, max) = (parse_cell(min)?, parse_cell(max)?);
+                if min > max {
+                    return Err(SnapshotError::Invalid(
+                        "the minimum cell value is above the maximum".to_owned(),
+                    ));
+                }
+                (min, max)
+            }
+        };
+        let overflow_mode = parser.value("overflow")?;
+        let eof_behaviour = parser.value("eof")?;
+        let data = parser
+            .field("tape")?
+            .split_ascii_whitespace()
+            .map(parse_cell)
+            .collect::<Result<_, _>>()?;
+        let source = String::from_utf8(parser.block("source")?.to_vec())
+            .map_err(|_| SnapshotError::Invalid("the source is not valid UTF-8".to_owned()))?;
+        let input = parser.block("input")?.to_vec();
+        let output = parser.block("output")?.to_vec();
+        if !parser.rest.is_empty() {
+            return Err(SnapshotError::Invalid(
+                "unexpected data after the output".to_owned(),
+            ));
+        }
+
+        let snapshot = Self {
+            source,
+            instruction_pointer,
+            cycles,
+            data,
+            data_pointer,
+            origin,
+            tape_policy,
+            max_tape_length,
+            min_cell_value,
+            max_cell_value,
+            overflow_mode,
+            eof_behaviour,
+            input,
+            output,
+        };
+        snapshot.check_tape()?;
+        Ok(snapshot)
+    }
+
+    /// Checks that the data pointer and origin are somewhere the tape policy allows, since the
+    /// rest of the runtime relies on that.
+    fn check_tape(&self) -> Result<(), SnapshotError> {
+        if self.data_pointer >= self.data.len() {
+            return Err(SnapshotError::Invalid(format!(
+                "data pointer {} is outside of the tape ({} cells)",
+                self.data_pointer,
+                self.data.len()
+            )));
+        }
+        if self.origin > self.data.len() {
+            return Err(SnapshotError::Invalid(format!(
+                "origin {} is past the end of the tape ({} cells)",
+                self.origin,
+                self.data.len()
+            )));
+        }
+        if self.origin != 0 && self.tape_policy != TapePolicy::Bidirectional {
+            return Err(SnapshotError::Invalid(format!(
+                "origin {} on a {} tape, which has no cells before the origin",
+                self.origin, self.tape_policy
+            )));
+        }
+        let limit = match self.tape_policy {
+            TapePolicy::Circular => Some(
+                self.max_tape_length
+                    .unwrap_or(DEFAULT_CIRCULAR_TAPE_LENGTH)
+                    .max(1),
+            ),
+            _ => self.max_tape_length,
+        };
+        if let Some(limit) = limit {
+            if self.data.len() > limit {
+                return Err(SnapshotError::Invalid(format!(
+                    "the tape has {} cells, more than its length of {}",
+                    self.data.len(),
+                    limit
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_block(writer: &mut impl Write, name: &str, bytes: &[u8]) -> io::Result<()> {
+    writeln!(writer, "{} {}", name, bytes.len())?;
+    writer.write_all(bytes)?;
+    writeln!(writer)
+}
+
+fn parse_value<V: FromStr>(name: &str, value: &str) -> Result<V, SnapshotError> {
+    value
+        .parse()
+        .map_err(|_| SnapshotError::Invalid(format!("invalid value '{}' for {}", value, name)))
+}
+
+fn parse_cell<T: CellType>(value: &str) -> Result<T, SnapshotError> {
+    T::from_str_radix(value, 10)
+        .map_err(|_| SnapshotError::Invalid(format!("cell value {} is out of range", value)))
+}
+
+/// Reads the fields of a snapshot in order.
+struct Parser<'a> {
+    rest: &'a [u8],
+}
+impl<'a> Parser<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.rest.len() < len {
+            return Err(SnapshotError::Invalid("unexpected end of file".to_owned()));
+        }
+        let (taken, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        Ok(taken)
+    }
+    fn line(&mut self) -> Result<&'a str, SnapshotError> {
+        let len = self
+            .rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| SnapshotError::Invalid("unexpected end of file".to_owned()))?;
+        let line = self.take(len)?;
+        self.take(1)?;
+        std::str::from_utf8(line)
+            .map_err(|_| SnapshotError::Invalid("a line is not valid UTF-8".to_owned()))
+    }
+    /// Reads the line of the field called `name` and returns its value.
+    fn field(&mut self, name: &str) -> Result<&'a str, SnapshotError> {
+        let line = self.line()?;
+        let (field, value) = line.split_once(' ').unwrap_or((line, ""));
+        if field != name {
+            return Err(SnapshotError::Invalid(format!(
+                "expected {} but found '{}'",
+                name, field
+            )));
+        }
+        Ok(value)
+    }
+    fn value<V: FromStr>(&mut self, name: &str) -> Result<V, SnapshotError> {
+        parse_value(name, self.field(name)?)
+    }
+    fn block(&mut self, name: &str) -> Result<&'a [u8], SnapshotError> {
+        let len = self.value(name)?;
+        let bytes = self.take(len)?;
+        if self.take(1)? != b"\n" {
+            return Err(SnapshotError::Invalid(format!(
+                "{} is longer than its length",
+                name
+            )));
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_bigint::BigInt;
+
+    fn snapshot<T: CellType>(data: &[i64], tape_policy: TapePolicy) -> Snapshot<T> {
+        let cell = |value: &i64| T::from_str_radix(&value.to_string(), 10).expect("cell fits");
+        Snapshot {
+            source: "+[>,.<-]".to_owned(),
+            instruction_pointer: 3,
+            cycles: 42,
+            data: data.iter().map(cell).collect(),
+            data_pointer: 1,
+            origin: if tape_policy == TapePolicy::Bidirectional {
+                1
+            } else {
+                0
+            },
+            tape_policy,
+            max_tape_length: Some(10),
+            min_cell_value: T::min_value(),
+            max_cell_value: T::max_value(),
+            overflow_mode: OverflowMode::Saturate,
+            eof_behaviour: EofBehaviour::Unchanged,
+            input: b"rest\nof input".to_vec(),
+            output: vec![0, 10, 255],
+        }
+    }
+
+    fn write<T: CellType>(snapshot: &Snapshot<T>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        snapshot
+            .write_to(&mut bytes)
+            .expect("writing to memory works");
+        bytes
+    }
+
+    fn round_trip<T: CellType + std::fmt::Debug>(data: &[i64]) {
+        for tape_policy in [
+            TapePolicy::LeftBounded,
+            TapePolicy::Bidirectional,
+            TapePolicy::Circular,
+        ] {
+            let snapshot = snapshot::<T>(data, tape_policy);
+            let bytes = write(&snapshot);
+            let read = Snapshot::<T>::read_from(&bytes[..]).expect("snapshot loads");
+            assert_eq!(read.data, snapshot.data);
+            assert_eq!(read.tape_policy, tape_policy);
+            assert_eq!(read.origin, snapshot.origin);
+            assert_eq!(read.input, snapshot.input);
+            assert_eq!(read.output, snapshot.output);
+            assert_eq!(write(&read), bytes);
+        }
+    }
+
+    #[test]
+    fn snapshots_round_trip() {
+        round_trip::<u8>(&[0, 255, 7]);
+        round_trip::<u16>(&[0, 65535, 7]);
+        round_trip::<u32>(&[0, u32::MAX as i64, 7]);
+        round_trip::<u64>(&[0, i64::MAX, 7]);
+        round_trip::<i8>(&[-128, 127, -1]);
+        round_trip::<i16>(&[-32768, 32767, -1]);
+        round_trip::<i32>(&[i32::MIN as i64, i32::MAX as i64, -1]);
+        round_trip::<i64>(&[i64::MIN, i64::MAX, -1]);
+        round_trip::<BigInt>(&[i64::MIN, i64::MAX, -1]);
+    }
+
+    fn error(bytes: &[u8]) -> SnapshotError {
+        Snapshot::<u8>::read_from(bytes).expect_err("snapshot is rejected")
+    }
+
+    #[test]
+    fn truncated_snapshots_are_rejected() {
+        let bytes = write(&snapshot::<u8>(&[1, 2, 3], TapePolicy::LeftBounded));
+        for len in 0..bytes.len() {
+            let error = error(&bytes[..len]);
+            assert!(matches!(error, SnapshotError::Invalid(_)), "{}", len);
+        }
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let mut bytes = write(&snapshot::<u8>(&[1, 2, 3], TapePolicy::LeftBounded));
+        assert!(bytes.starts_with(b"brainfuck snapshot 1\n"));
+        bytes[MAGIC.len() + 1] = b'2';
+        assert!(matches!(
+            error(&bytes),
+            SnapshotError::UnsupportedVersion(2)
+        ));
+    }
+
+    #[test]
+    fn tapes_out_of_range_are_rejected() {
+        let invalid = |change: fn(&mut Snapshot<u8>)| {
+            let mut snapshot = snapshot::<u8>(&[1, 2, 3], TapePolicy::Bidirectional);
+            change(&mut snapshot);
+            let result = Snapshot::<u8>::read_from(&write(&snapshot)[..]);
+            matches!(result, Err(SnapshotError::Invalid(_)))
+        };
+        assert!(invalid(|s| s.data_pointer = 3));
+        assert!(invalid(|s| s.data_pointer = usize::MAX));
+        assert!(invalid(|s| s.origin = 4));
+        assert!(invalid(|s| s.tape_policy = TapePolicy::LeftBounded));
+        assert!(invalid(|s| s.max_tape_length = Some(2)));
+        assert!(!invalid(|s| s.origin = 3));
+    }
+
+    #[test]
+    fn cells_out_of_range_are_rejected() {
+        let bytes = write(&snapshot::<u16>(&[1, 256, 3], TapePolicy::LeftBounded));
+        assert!(matches!(error(&bytes), SnapshotError::Invalid(_)));
+        let bytes = write(&snapshot::<i8>(&[1, -1, 3], TapePolicy::LeftBounded));
+        assert!(matches!(error(&bytes), SnapshotError::Invalid(_)));
+    }
+}
@@ -0,0 +1,271 @@
+//! Compiles scripts with every backend, runs the results and checks that they behave like the
+//! interpreter.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Scripts and their input. Input ends with a zero, since the interpreter can't run out of it.
+const CASES: &[(&str, &[u8])] = &[
+    (include_str!("../examples/hello-world.bf"), b""),
+    (include_str!("../examples/hello-world-compact.bf"), b""),
+    (include_str!("../examples/addition.bf"), b""),
+    (include_str!("../examples/cell-size.bf"), b""),
+    (",[.,]", b"echo\0"),
+    ("++++++++[>++++++++<-]>+.", b""),
+    (
+        "++++[>+++<-]>[<++>-]<[>>+>+<<<-]>>>[>]+++[<++++++>-]<.",
+        b"",
+    ),
+    ("+>>>+[<]>+++++[>+++++++++++++<-]>.", b""),
+    ("-[+]+++++++[>++++++++++<-]>.", b""),
+];
+
+fn brainfuck() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_brainfuck"))
+}
+
+/// Runs the command with `input` on stdin and returns its stdout, failing the test if it fails.
+fn run(command: &mut Command, input: &[u8]) -> Vec<u8> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("command starts");
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(input)
+        .expect("input is written");
+    let output = child.wait_with_output().expect("command finishes");
+    assert!(
+        output.status.success(),
+        "{:?} failed: {}",
+        command,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output.stdout
+}
+
+fn interpret(source: &str, input: &[u8]) -> Vec<u8> {
+    run(brainfuck().args(["--engine", "step", "--", source]), input)
+}
+
+fn compile(source: &str, args: &[&str]) -> Vec<u8> {
+    run(
+        brainfuck().arg("compile").args(args).arg("-"),
+        source.as_bytes(),
+    )
+}
+
+fn work_dir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::create_dir_all(&dir).expect("work directory is created");
+    dir
+}
+
+fn available(tool: &str) -> bool {
+    let found = Command::new(tool).arg("--version").output().is_ok();
+    if !found {
+        eprintln!("skipping: {} not found", tool);
+    }
+    found
+}
+
+#[test]
+fn c_source() {
+    let source = String::from_utf8(compile("+[->++<]>.", &["--target", "c"])).unwrap();
+    let (_, body) = source.split_once("size_t p = 0;\n").unwrap();
+    assert_eq!(
+        body,
+        "    tape[p] += (cell_t)1LL;
+    if (tape[p]) tape[offset(p, 1LL)] += (cell_t)((uint64_t)tape[p] * (uint64_t)2LL);
+    tape[p] = 0;
+    p = offset(p, 1LL);
+    output(tape[p]);
+    fflush(stdout);
+    return 0;
+}
+"
+    );
+}
+
+#[test]
+fn c_matches_interpreter() {
+    if !available("cc") {
+        return;
+    }
+    let dir = work_dir("c");
+    for (i, (source, input)) in CASES.iter().enumerate() {
+        let file = dir.join(format!("{}.c", i));
+        let executable = dir.join(i.to_string());
+        std::fs::write(&file, compile(source, &["--target", "c"])).unwrap();
+        run(
+            Command::new("cc").arg("-o").arg(&executable).arg(&file),
+            b"",
+        );
+        assert_eq!(
+            run(&mut Command::new(&executable), input),
+            interpret(source, input),
+            "script: {:?}",
+            source
+        );
+    }
+}
+
+#[test]
+fn rust_source() {
+    let source = String::from_utf8(compile("+[->++<]>.", &["--target", "rust"])).unwrap();
+    let (_, body) = source.split_once("let mut p = 0usize;\n").unwrap();
+    assert_eq!(
+        body,
+        "    tape[p] = offset_cell(tape[p], 1);
+    if tape[p] != 0 {
+        let target = move_pointer(p, 1)?;
+        tape[target] = offset_cell(tape[target], tape[p] as i128 * 2);
+    }
+    tape[p] = 0;
+    p = move_pointer(p, 1)?;
+    output.write_all(&tape[p].to_be_bytes())?;
+    output.flush()
+}
+"
+    );
+}
+
+#[test]
+fn rust_matches_interpreter() {
+    if !available("rustc") {
+        return;
+    }
+    let dir = work_dir("rust");
+    for (i, (source, input)) in CASES.iter().enumerate() {
+        let file = dir.join(format!("{}.rs", i));
+        let executable = dir.join(i.to_string());
+        std::fs::write(&file, compile(source, &["--target", "rust-main"])).unwrap();
+        run(
+            Command::new("rustc")
+                .args(["--edition", "2021", "-o"])
+                .arg(&executable)
+                .arg(&file),
+            b"",
+        );
+        assert_eq!(
+            run(&mut Command::new(&executable), input),
+            interpret(source, input),
+            "script: {:?}",
+            source
+        );
+    }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn elf_matches_interpreter() {
+    let dir = work_dir("elf");
+    for (i, (source, input)) in CASES.iter().enumerate() {
+        let executable = dir.join(i.to_string());
+        // Written by the compiler rather than by this process, which may be forking for other
+        // tests while it has the file open, and then couldn't execute it.
+        run(
+            brainfuck()
+                .args(["compile", "--target", "elf", "-o"])
+                .arg(&executable)
+                .arg("-"),
+            source.as_bytes(),
+        );
+        assert_eq!(
+            run(&mut Command::new(&executable), input),
+            interpret(source, input),
+            "script: {:?}",
+            source
+        );
+    }
+}
+
+const CELL_KINDS: [&str; 4] = ["u8", "u16", "u32", "u64"];
+
+#[test]
+fn wasm_modules_are_valid() {
+    let validate = |module: &[u8]| {
+        wasmparser::Validator::new()
+            .validate_all(module)
+            .expect("the module is valid");
+    };
+    for (source, _) in CASES {
+        for cell in CELL_KINDS {
+            let binary = compile(source, &["--target", "wasm", "--cell", cell]);
+            validate(&binary);
+            let text = compile(source, &["--target", "wat", "--cell", cell]);
+            let text = String::from_utf8(text).unwrap();
+            let binary = wat::parse_str(text).expect("the text parses");
+            validate(&binary);
+        }
+    }
+}
+
+/// Instantiates the module in the file given as the first argument and runs it on stdin and
+/// stdout. The end of input reads as 0.
+const WASM_RUNNER: &str = r#"
+const fs = require("fs");
+const input = fs.readFileSync(0);
+let position = 0;
+const output = [];
+const wide = process.argv[2] === "u64";
+const env = {
+    read: () => {
+        const value = position < input.length ? input[position++] : 0;
+        return wide ? BigInt(value) : value;
+    },
+    write: (value) => output.push(Number(value) & 0xff),
+};
+const module = new WebAssembly.Module(fs.readFileSync(process.argv[1]));
+new WebAssembly.Instance(module, { env }).exports.run();
+process.stdout.write(Buffer.from(output));
+"#;
+
+fn run_wasm(dir: &Path, name: &str, source: &str, cell: &str, input: &[u8]) -> Vec<u8> {
+    let module = dir.join(format!("{}-{}.wasm", name, cell));
+    run(
+        brainfuck()
+            .args(["compile", "--target", "wasm", "--cell", cell, "-o"])
+            .arg(&module)
+            .arg("-"),
+        source.as_bytes(),
+    );
+    run(
+        Command::new("node")
+            .args(["-e", WASM_RUNNER])
+            .arg(&module)
+            .arg(cell),
+        input,
+    )
+}
+
+#[test]
+fn wasm_matches_interpreter() {
+    if !available("node") {
+        return;
+    }
+    let dir = work_dir("wasm");
+    for (i, (source, input)) in CASES.iter().enumerate() {
+        assert_eq!(
+            run_wasm(&dir, &i.to_string(), source, "u8", input),
+            interpret(source, input),
+            "script: {:?}",
+            source
+        );
+    }
+    // Cells wider than 32 bits look like 32-bit ones to this script.
+    let cell_size = include_str!("../examples/cell-size.bf");
+    for (cell, bits) in CELL_KINDS.iter().zip(["8", "16", "32", "32"]) {
+        assert_eq!(
+            run_wasm(&dir, "cell-size", cell_size, cell, b""),
+            format!("{} bit cells\n", bits).into_bytes(),
+            "cells: {}",
+            cell
+        );
+    }
+}